@@ -0,0 +1,154 @@
+//! Measures the throughput of the mock server with thousands of routes.
+//!
+//! The benchmark writes the route files into a temporary directory, starts the `moker` binary on them and sends
+//! requests to random routes over keep-alive connections for a few seconds.
+//!
+//! ```bash
+//! cargo bench --bench throughput
+//! MOKER_BENCH_ROUTES=10000 MOKER_BENCH_CONNECTIONS=32 cargo bench --bench throughput
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Reads a numeric setting from the environment.
+fn setting(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Writes one route file per route, each with a path parameter so every request goes through the regex matching.
+fn write_routes(directory: &Path, routes: usize) {
+    fs::create_dir_all(directory).unwrap();
+    for index in 0..routes {
+        let route = format!(
+            r#"{{"url": "bench/{index}/items/{{id}}", "method": "GET", "response": {{"body": {{"route": {index}}}}}}}"#
+        );
+        fs::write(directory.join(format!("route_{}.json", index)), route).unwrap();
+    }
+}
+
+/// Returns a port that is free at the time of the call.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Sends a request on a keep-alive connection and reads the response, returning its status code.
+fn send_request(reader: &mut BufReader<TcpStream>, path: &str) -> Option<u16> {
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    reader.get_mut().write_all(request.as_bytes()).ok()?;
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).ok()?;
+    let status = status_line.split_whitespace().nth(1)?.parse().ok()?;
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+    Some(status)
+}
+
+fn main() {
+    let routes = setting("MOKER_BENCH_ROUTES", 5000);
+    let connections = setting("MOKER_BENCH_CONNECTIONS", 8);
+    let duration = Duration::from_secs(setting("MOKER_BENCH_SECONDS", 5) as u64);
+
+    let directory = env::temp_dir().join(format!("moker_bench_{}", std::process::id()));
+    write_routes(&directory, routes);
+
+    let port = free_port();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_moker"))
+        .arg("-s")
+        .arg(&directory)
+        .arg("-p")
+        .arg(port.to_string())
+        .env("RUST_LOG", "error")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Unable to start moker");
+
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(30),
+            "moker didn't start"
+        );
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let workers: Vec<_> = (0..connections)
+        .map(|worker| {
+            thread::spawn(move || {
+                let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+                let mut reader = BufReader::new(stream);
+                // A small linear congruential generator is enough to spread the requests over the routes
+                let mut seed = worker as u64 + 1;
+                let (mut succeeded, mut failed) = (0u64, 0u64);
+
+                let started = Instant::now();
+                while started.elapsed() < duration {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    let route = (seed >> 33) as usize % routes;
+                    match send_request(&mut reader, &format!("/bench/{}/items/42", route)) {
+                        Some(200) => succeeded += 1,
+                        Some(_) => failed += 1,
+                        None => {
+                            failed += 1;
+                            let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+                            reader = BufReader::new(stream);
+                        }
+                    }
+                }
+                (succeeded, failed)
+            })
+        })
+        .collect();
+
+    let (succeeded, failed) = workers
+        .into_iter()
+        .map(|worker| worker.join().unwrap())
+        .fold((0, 0), |(a, b), (c, d)| (a + c, b + d));
+
+    _ = server.kill();
+    _ = server.wait();
+    _ = fs::remove_dir_all(&directory);
+
+    let seconds = duration.as_secs_f64();
+    println!(
+        "{} routes, {} connections, {:.0?}: {} requests ({:.0} req/s), {} failed",
+        routes,
+        connections,
+        duration,
+        succeeded,
+        succeeded as f64 / seconds,
+        failed
+    );
+}
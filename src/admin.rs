@@ -0,0 +1,389 @@
+use crate::app_state::{AppState, LoadedFile, RouteTable};
+use crate::file_reader;
+use crate::file_watcher;
+use crate::journal::{Journal, JournalFilter, Verification};
+use crate::openapi::generate_openapi;
+use crate::refs;
+use crate::request::{Responses, RouteConfiguration};
+use crate::request_handler;
+use crate::rex::generate_regex_from_route;
+use actix_web::error::InternalError;
+use actix_web::web::{self, Data, Json, Path, Query};
+use actix_web::{HttpResponse, Responder};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The reserved URL prefix under which moker exposes its own endpoints.
+pub const ADMIN_SCOPE: &str = "/__moker";
+
+/// The page of the web dashboard, which only talks to the admin endpoints.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Enables or disables a route.
+#[derive(Debug, Deserialize)]
+struct RouteToggle {
+    /// The cache key of the route, as listed by `GET /__moker/routes`.
+    key: String,
+    enabled: bool,
+}
+
+/// Replaces the response of a route.
+#[derive(Debug, Deserialize)]
+struct ResponseEdit {
+    /// The cache key of the route, as listed by `GET /__moker/routes`.
+    key: String,
+    /// The new response, or sequence of responses.
+    response: Responses,
+    /// Whether the response of a file route is written to its file, rather than served by a runtime route.
+    #[serde(default)]
+    write_back: bool,
+}
+
+/// Selects a route by key.
+#[derive(Debug, Deserialize)]
+struct RouteKey {
+    /// The cache key of the route, as listed by `GET /__moker/routes`.
+    key: String,
+}
+
+/// Selects the response sequence counters to reset.
+#[derive(Debug, Deserialize)]
+struct SequenceKey {
+    /// The cache key of the route. Every counter is reset without it.
+    key: Option<String>,
+}
+
+/// Registers the admin endpoints on an Actix Web service configuration.
+///
+/// # Arguments
+///
+/// * `cfg` - The service configuration the admin scope is added to.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope(ADMIN_SCOPE)
+            .app_data(web::JsonConfig::default().error_handler(|err, _| {
+                let response = error_response(err.to_string());
+                InternalError::from_response(err, response).into()
+            }))
+            .route("/ui", web::get().to(dashboard))
+            .route("/openapi", web::get().to(openapi))
+            .route("/metrics", web::get().to(metrics))
+            .route("/routes", web::get().to(list_routes))
+            .route("/routes", web::post().to(add_route))
+            .route("/routes/{id}", web::put().to(replace_route))
+            .route("/routes/{id}", web::delete().to(delete_route))
+            .route("/toggle", web::post().to(toggle_route))
+            .route("/response", web::get().to(file_response))
+            .route("/response", web::put().to(edit_response))
+            .route("/errors", web::get().to(list_errors))
+            .route("/sequences", web::get().to(list_sequences))
+            .route("/sequences", web::delete().to(reset_sequences))
+            .route("/reset", web::post().to(reset))
+            .route("/cache", web::delete().to(clear_cache))
+            .route("/journal", web::get().to(list_journal))
+            .route("/journal", web::delete().to(clear_journal))
+            .route("/journal/verify", web::post().to(verify_journal)),
+    );
+}
+
+/// Serves the web dashboard.
+async fn dashboard() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(DASHBOARD)
+}
+
+/// Returns the currently loaded routes as an OpenAPI 3 document.
+async fn openapi(state: Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(generate_openapi(
+        &state.routes.load().map,
+        &state.server_url,
+    ))
+}
+
+/// Returns the metrics of the server in the Prometheus text format.
+async fn metrics(state: Data<AppState>) -> impl Responder {
+    let text = state.metrics.render(&state.routes.load());
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(text)
+}
+
+/// Lists the routes being served, runtime routes first, with the file they come from.
+///
+/// Every route has a `key`, used to toggle it and edit its response.
+async fn list_routes(state: Data<AppState>) -> impl Responder {
+    let routes = state.routes.load();
+
+    let runtime_routes = routes.runtime_routes.iter().map(|runtime| {
+        let key = format!("runtime#{}", runtime.id);
+        json!({
+            "id": runtime.id,
+            "source": "runtime",
+            "enabled": !routes.disabled.contains(&key),
+            "key": key,
+            "route": *runtime.route,
+        })
+    });
+    let disabled = &routes.disabled;
+    let file_routes = routes.loaded_files.iter().flat_map(|file| {
+        let file_name = file.response_file_type.file_name().unwrap_or_default();
+        file.routes.iter().enumerate().map(move |(index, route)| {
+            let key = format!("{}#{}", file_name, index);
+            json!({
+                "source": file_name,
+                "index": index,
+                "enabled": !disabled.contains(&key),
+                "key": key,
+                "route": **route,
+            })
+        })
+    });
+
+    HttpResponse::Ok().json(runtime_routes.chain(file_routes).collect::<Vec<Value>>())
+}
+
+/// Adds a route served before the routes of the files.
+async fn add_route(state: Data<AppState>, route: Json<RouteConfiguration>) -> HttpResponse {
+    let route = route.into_inner();
+    if let Err(message) = check_route(&route) {
+        return error_response(message);
+    }
+    let id = state.add_runtime_route(route);
+    HttpResponse::Created().json(json!({ "id": id }))
+}
+
+/// Replaces a route added at runtime.
+async fn replace_route(
+    state: Data<AppState>,
+    id: Path<u64>,
+    route: Json<RouteConfiguration>,
+) -> HttpResponse {
+    let route = route.into_inner();
+    if let Err(message) = check_route(&route) {
+        return error_response(message);
+    }
+    match state.replace_runtime_route(*id, route) {
+        true => HttpResponse::Ok().json(json!({ "id": *id })),
+        false => not_found(*id),
+    }
+}
+
+/// Deletes a route added at runtime.
+async fn delete_route(state: Data<AppState>, id: Path<u64>) -> HttpResponse {
+    match state.remove_runtime_route(*id) {
+        true => HttpResponse::NoContent().finish(),
+        false => not_found(*id),
+    }
+}
+
+/// Enables or disables a route. Disabled routes stay disabled when the files are reloaded, until the routes are reset.
+async fn toggle_route(state: Data<AppState>, toggle: Json<RouteToggle>) -> HttpResponse {
+    match state.set_enabled(&toggle.key, toggle.enabled) {
+        true => HttpResponse::Ok().json(json!({ "key": toggle.key, "enabled": toggle.enabled })),
+        false => unknown_key(&toggle.key),
+    }
+}
+
+/// Replaces the response of a route.
+///
+/// Runtime routes are replaced. File routes are shadowed by a runtime route serving the new response, or have the
+/// response written to their file and reloaded when `write_back` is set.
+async fn edit_response(state: Data<AppState>, edit: Json<ResponseEdit>) -> HttpResponse {
+    let ResponseEdit {
+        key,
+        response,
+        write_back,
+    } = edit.into_inner();
+    let routes = state.routes.load_full();
+
+    if let Some(runtime) = routes
+        .runtime_routes
+        .iter()
+        .find(|runtime| format!("runtime#{}", runtime.id) == key)
+    {
+        let route = RouteConfiguration {
+            response,
+            ..(*runtime.route).clone()
+        };
+        state.replace_runtime_route(runtime.id, route);
+        return HttpResponse::Ok().json(json!({ "id": runtime.id }));
+    }
+
+    let Some((file, route)) = find_file_route(&routes, &key) else {
+        return unknown_key(&key);
+    };
+
+    if write_back {
+        if let Err(err) = file_reader::write_response(&file.response_file_type, &response) {
+            return error_response(format!("unable to write to {}: {}", key, err));
+        }
+        let file_name = file.response_file_type.file_name().unwrap_or_default();
+        let changed = HashSet::from([refs::canonical(std::path::Path::new(file_name))]);
+        file_watcher::reload_changed_routes(&state, &changed);
+        return HttpResponse::Ok().json(json!({ "key": key }));
+    }
+    let id = state.add_runtime_route(RouteConfiguration {
+        response,
+        ..(**route).clone()
+    });
+    HttpResponse::Created().json(json!({ "id": id }))
+}
+
+/// Returns the response of a file route as written in its file, the one replaced when it is written back.
+///
+/// Answers `400` with the reason if the response can't be written back, for example because it includes fragments.
+async fn file_response(state: Data<AppState>, route: Query<RouteKey>) -> HttpResponse {
+    let routes = state.routes.load();
+    let Some((file, _)) = find_file_route(&routes, &route.key) else {
+        return unknown_key(&route.key);
+    };
+    match file_reader::read_response(&file.response_file_type) {
+        Ok(response) => HttpResponse::Ok().json(json!({ "key": route.key, "response": response })),
+        Err(err) => error_response(err.to_string()),
+    }
+}
+
+/// Lists the route files that couldn't be read the last time they were loaded, with the error.
+async fn list_errors(state: Data<AppState>) -> impl Responder {
+    let routes = state.routes.load();
+    let errors: Vec<Value> = routes
+        .loaded_files
+        .iter()
+        .filter_map(|file| {
+            Some(json!({
+                "source": file.response_file_type.file_name(),
+                "error": file.error.as_ref()?,
+            }))
+        })
+        .collect();
+    HttpResponse::Ok().json(errors)
+}
+
+/// Lists how many requests each route with a response sequence has served, by key.
+async fn list_sequences(state: Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(&*state.sequence_calls.lock().unwrap())
+}
+
+/// Restarts the response sequence of a route, or of every route, from its first response.
+async fn reset_sequences(state: Data<AppState>, sequence: Query<SequenceKey>) -> impl Responder {
+    let mut sequence_calls = state.sequence_calls.lock().unwrap();
+    match &sequence.key {
+        Some(key) => _ = sequence_calls.remove(key),
+        None => sequence_calls.clear(),
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Drops the runtime routes and the journal, and reads the route files again, as if moker had just started.
+async fn reset(state: Data<AppState>, journal: Data<Journal>) -> impl Responder {
+    let loaded_files = request_handler::load_route_files(
+        &state.search_paths,
+        &state.scan_options,
+        &state.match_options,
+        &[],
+    );
+    state.reset(loaded_files);
+    journal.clear();
+    HttpResponse::NoContent().finish()
+}
+
+/// Clears the route cache of earlier versions.
+///
+/// Deprecated: routes are served from the snapshot swapped in by reloads, there is no cache left to clear.
+/// Kept so existing scripts don't break, it answers `204` and does nothing.
+async fn clear_cache() -> impl Responder {
+    HttpResponse::NoContent().finish()
+}
+
+/// Lists the recorded requests matching the query parameters, oldest first.
+async fn list_journal(journal: Data<Journal>, filter: Query<JournalFilter>) -> HttpResponse {
+    match journal.entries(&filter) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(message) => error_response(message),
+    }
+}
+
+/// Removes every recorded request.
+async fn clear_journal(journal: Data<Journal>) -> impl Responder {
+    journal.clear();
+    HttpResponse::NoContent().finish()
+}
+
+/// Checks the number of recorded requests matching a filter.
+///
+/// Answers `200` when the expectation is met and `417` otherwise, so scripts can rely on the status code.
+async fn verify_journal(journal: Data<Journal>, verification: Json<Verification>) -> HttpResponse {
+    match journal.verify(&verification) {
+        Ok(result) if result.verified => HttpResponse::Ok().json(result),
+        Ok(result) => HttpResponse::ExpectationFailed().json(result),
+        Err(message) => error_response(message),
+    }
+}
+
+/// Checks that a route can be served, and doesn't shadow the admin endpoints.
+fn check_route(route: &RouteConfiguration) -> Result<(), String> {
+    let url = route.url.trim_matches('/');
+    if url == &ADMIN_SCOPE[1..] || url.starts_with(&format!("{}/", &ADMIN_SCOPE[1..])) {
+        return Err(format!("'{}' is reserved for moker", ADMIN_SCOPE));
+    }
+    generate_regex_from_route(url)
+        .map(|_| ())
+        .map_err(|err| format!("invalid url '{}': {}", route.url, err))
+}
+
+/// Finds the file route with a key, and the file holding it.
+fn find_file_route<'a>(
+    routes: &'a RouteTable,
+    key: &str,
+) -> Option<(&'a LoadedFile, &'a Arc<RouteConfiguration>)> {
+    let (file_name, index) = key.rsplit_once('#')?;
+    let file = routes
+        .loaded_files
+        .iter()
+        .find(|file| file.response_file_type.file_name() == Some(file_name))?;
+    Some((file, file.routes.get(index.parse::<usize>().ok()?)?))
+}
+
+fn error_response(message: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({ "error": message }))
+}
+
+fn unknown_key(key: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("no route with key {}", key) }))
+}
+
+fn not_found(id: u64) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("no runtime route with id {}", id) }))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::app_state::{MatchOptions, ResponseDefaults};
+    use crate::file_reader::ScanOptions;
+    use actix_web::{test, App};
+
+    #[actix_web::test]
+    async fn test_clear_cache() {
+        let state = Data::new(AppState::new(
+            vec![],
+            None,
+            MatchOptions::default(),
+            vec![],
+            ScanOptions::default(),
+            ResponseDefaults::default(),
+        ));
+        let app = test::init_service(App::new().app_data(state).configure(configure)).await;
+
+        let request = test::TestRequest::delete()
+            .uri("/__moker/cache")
+            .to_request();
+        let response = test::call_service(&app, request).await;
+
+        assert_eq!(response.status(), actix_web::http::StatusCode::NO_CONTENT);
+    }
+}
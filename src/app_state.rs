@@ -1,15 +1,206 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
-use crate::cache::Cache;
+use crate::file_reader::{ScanOptions, SearchPath};
+use crate::metrics::Metrics;
+use crate::request::{Response, RouteConfiguration};
+use crate::rex::generate_regex_from_route;
+use arc_swap::ArcSwap;
+use log::{info, warn};
+use regex::Regex;
 
-/// Represents the application state containing configuration mappings and the server port.
-pub struct AppState {
-    /// A thread-safe container for storing route-to-configuration mappings.
-    pub config_map: Mutex<HashMap<String, RequestHandlingConfig>>,
-    /// The port on which the server will run.
-    pub port: u16,
+/// Maps every route URL to the configurations that can answer it.
+///
+/// A URL can be served by several configurations, for example one per HTTP method; they are tried in order.
+pub type RouteMap = HashMap<String, Vec<RequestHandlingConfig>>;
+
+/// The routes last read successfully from a route file.
+///
+/// When a file is changed into one that can't be read, its last good version keeps being served.
+#[derive(Debug, Clone)]
+pub struct LoadedFile {
+    /// The type and location of the file.
+    pub response_file_type: ResponseFileType,
+    /// The routes of the file, in order, with their defaults applied. Requests are answered from them directly.
+    pub routes: Vec<Arc<RouteConfiguration>>,
+    /// Why the file couldn't be read the last time it was loaded. Its last good routes, if any, are kept.
+    pub error: Option<String>,
+    /// The files whose changes affect the routes, as canonical paths: the overlay of the selected profile and the
+    /// files referenced with `$ref`, as of the last time the file was read.
+    pub dependencies: HashSet<PathBuf>,
+}
+
+impl LoadedFile {
+    /// Returns `true` if both versions of a file define the same routes.
+    fn same_routes(&self, other: &LoadedFile) -> bool {
+        self.routes.len() == other.routes.len()
+            && self.routes.iter().zip(&other.routes).all(|(route, other)| {
+                Arc::ptr_eq(route, other)
+                    || serde_json::to_value(&**route).ok() == serde_json::to_value(&**other).ok()
+            })
+    }
+}
+
+/// A route added through the admin API, kept apart from the routes of the files so reloads don't drop it.
+#[derive(Debug, Clone)]
+pub struct RuntimeRoute {
+    /// The identifier used to replace or delete the route.
+    pub id: u64,
+    /// The route, shared with the route map.
+    pub route: Arc<RouteConfiguration>,
+}
+
+/// An immutable snapshot of the loaded routes.
+///
+/// Requests read the current snapshot without locking, and reloads replace it as a whole.
+pub struct RouteTable {
+    /// The route-to-configuration mappings.
+    pub map: RouteMap,
+    /// The URLs of the map with the regular expressions matching them, compiled once and sorted by URL.
+    /// URLs of runtime routes come first.
+    matchers: Vec<(String, Regex)>,
+    /// The routes last read successfully from every route file, in the order of the search paths.
+    pub loaded_files: Vec<LoadedFile>,
+    /// The routes added through the admin API, tried before the routes of the files.
+    pub runtime_routes: Vec<RuntimeRoute>,
+    /// The cache keys of the routes disabled through the admin API. They are kept across reloads.
+    pub disabled: HashSet<String>,
+}
+
+impl RouteTable {
+    /// Creates the snapshot of the routes of the loaded files.
+    ///
+    /// # Arguments
+    ///
+    /// * `loaded_files` - The files, in the order their routes are tried.
+    pub fn new(loaded_files: Vec<LoadedFile>) -> Self {
+        Self::build(loaded_files, vec![], None)
+    }
+
+    /// Creates a snapshot, reusing the regular expressions and the disabled routes of the previous one.
+    fn build(
+        loaded_files: Vec<LoadedFile>,
+        runtime_routes: Vec<RuntimeRoute>,
+        previous: Option<&RouteTable>,
+    ) -> Self {
+        let map = route_map(&runtime_routes, &loaded_files);
+        let previous_disabled = previous.map(|previous| &previous.disabled);
+        let previous: HashMap<&str, &Regex> = previous
+            .map(|previous| {
+                previous
+                    .matchers
+                    .iter()
+                    .map(|(url, regex)| (url.as_str(), regex))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut matchers: Vec<(String, Regex)> = map
+            .keys()
+            .filter_map(|url| match previous.get(url.as_str()) {
+                Some(regex) => Some((url.clone(), (*regex).clone())),
+                None => match generate_regex_from_route(url) {
+                    Ok(regex) => Some((url.clone(), regex)),
+                    Err(err) => {
+                        warn!("Unable to generate the regex for route {:?}: {}", url, err);
+                        None
+                    }
+                },
+            })
+            .collect();
+        let is_runtime = |url: &str| {
+            map[url].iter().any(|config| {
+                matches!(
+                    config.response_file_type,
+                    ResponseFileType::StaticResponse(_)
+                )
+            })
+        };
+        matchers.sort_by_cached_key(|(url, _)| (!is_runtime(url), url.clone()));
+
+        Self {
+            disabled: previous_disabled.cloned().unwrap_or_default(),
+            map,
+            matchers,
+            loaded_files,
+            runtime_routes,
+        }
+    }
 
-    pub cache: Mutex<Cache>,
+    /// Returns the routes whose URL matches a request path, with their configurations.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The request path, without leading and trailing slashes.
+    pub fn matching<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a [RequestHandlingConfig])> {
+        self.matchers
+            .iter()
+            .filter(move |(_, regex)| regex.is_match(path))
+            .filter_map(|(url, _)| Some((url.as_str(), self.map.get(url)?.as_slice())))
+    }
+}
+
+/// Builds the route map of the runtime routes and the loaded files.
+///
+/// Runtime routes come first, then files in the order they are given.
+///
+/// # Arguments
+///
+/// * `runtime_routes` - The routes added through the admin API.
+/// * `loaded_files` - The files, in the order their routes are tried.
+pub fn route_map(runtime_routes: &[RuntimeRoute], loaded_files: &[LoadedFile]) -> RouteMap {
+    let mut map = RouteMap::new();
+    for runtime_route in runtime_routes {
+        let config = RequestHandlingConfig::new(
+            ResponseFileType::StaticResponse(runtime_route.route.clone()),
+            runtime_route.id as usize,
+            runtime_route.route.clone(),
+        );
+        map.entry(runtime_route.route.url.trim_matches('/').to_string())
+            .or_default()
+            .push(config);
+    }
+    for file in loaded_files {
+        for (index, route) in file.routes.iter().enumerate() {
+            let config =
+                RequestHandlingConfig::new(file.response_file_type.clone(), index, route.clone());
+            map.entry(route.url.trim_matches('/').to_string())
+                .or_default()
+                .push(config);
+        }
+    }
+    map
+}
+
+/// Represents the application state containing configuration mappings and the server URL.
+pub struct AppState {
+    /// The current snapshot of the routes, swapped atomically when route files change.
+    pub routes: ArcSwap<RouteTable>,
+    /// The identifier of the next route added through the admin API.
+    next_runtime_id: AtomicU64,
+    /// The URL clients reach the server at, with its scheme, host and port.
+    pub server_url: String,
+    /// Options controlling how incoming requests are matched against route guards.
+    pub match_options: MatchOptions,
+    /// The directories the routes are loaded from, with their mount prefixes, read again when reloading.
+    pub search_paths: Vec<SearchPath>,
+    /// Options selecting which files of the search paths are loaded, used again when reloading.
+    pub scan_options: ScanOptions,
+    /// Defaults applied to every response, and the response served when no route answers.
+    pub response_defaults: ResponseDefaults,
+    /// The number of requests each route with a response sequence has served, by cache key.
+    pub sequence_calls: Mutex<HashMap<String, usize>>,
+    /// The counters exposed by the metrics endpoint.
+    pub metrics: Metrics,
 }
 
 impl AppState {
@@ -17,23 +208,276 @@ impl AppState {
     ///
     /// # Arguments
     ///
-    /// * `file_map` - A `HashMap` containing route-to-configuration mappings.
-    /// * `port` - An optional `u16` representing the server port. Defaults to `8080` if not provided.
+    /// * `loaded_files` - The route files read from the search paths, turned into the route map.
+    /// * `server_url` - The URL clients reach the server at. Defaults to `http://127.0.0.1:8080` if not provided.
+    /// * `match_options` - Options controlling how incoming requests are matched against route guards.
+    /// * `search_paths` - The directories the routes are loaded from.
+    /// * `scan_options` - Options selecting which files of the search paths are loaded.
+    /// * `response_defaults` - Defaults applied to every response.
     ///
     /// # Returns
     ///
     /// Returns a new `AppState` instance with the provided configurations.
     pub fn new(
-        file_map: HashMap<String, RequestHandlingConfig>,
-        port: Option<u16>,
-        capacity: usize,
+        loaded_files: Vec<LoadedFile>,
+        server_url: Option<String>,
+        match_options: MatchOptions,
+        search_paths: Vec<SearchPath>,
+        scan_options: ScanOptions,
+        response_defaults: ResponseDefaults,
     ) -> Self {
         Self {
-            config_map: Mutex::new(file_map),
-            port: port.unwrap_or(8080),
-            cache: Mutex::new(Cache::new(capacity)),
+            routes: ArcSwap::from_pointee(RouteTable::new(loaded_files)),
+            next_runtime_id: AtomicU64::new(1),
+            server_url: server_url.unwrap_or_else(|| String::from("http://127.0.0.1:8080")),
+            match_options,
+            search_paths,
+            scan_options,
+            response_defaults,
+            sequence_calls: Mutex::new(HashMap::new()),
+            metrics: Metrics::default(),
         }
     }
+
+    /// Replaces the loaded route files, so deleted files and changed URLs stop being served.
+    ///
+    /// Requests being handled keep the previous snapshot. The response sequence counters of the files that were
+    /// changed or removed are dropped once the new snapshot is in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `loaded_files` - The route files read again from the search paths.
+    pub fn reload(&self, loaded_files: Vec<LoadedFile>) {
+        self.metrics
+            .record_reload(loaded_files.iter().any(|file| file.error.is_some()));
+        let previous = self.routes.load_full();
+        let previous_files = &previous.loaded_files;
+
+        let mut changed = vec![];
+        for previous in previous_files.iter() {
+            let Some(file_name) = previous.response_file_type.file_name() else {
+                continue;
+            };
+            match loaded_files
+                .iter()
+                .find(|file| file.response_file_type.file_name() == Some(file_name))
+            {
+                Some(file) if file.same_routes(previous) => {}
+                Some(_) => {
+                    info!("Reloading the routes of {}", file_name);
+                    changed.push(file_name);
+                }
+                None => {
+                    info!("Removing the routes of {}", file_name);
+                    changed.push(file_name);
+                }
+            }
+        }
+        for file in &loaded_files {
+            let file_name = file.response_file_type.file_name();
+            if !previous_files
+                .iter()
+                .any(|previous| previous.response_file_type.file_name() == file_name)
+            {
+                info!("Adding the routes of {}", file_name.unwrap_or_default());
+            }
+        }
+
+        self.routes.rcu(|current| {
+            RouteTable::build(
+                loaded_files.clone(),
+                current.runtime_routes.clone(),
+                Some(current),
+            )
+        });
+
+        let mut sequence_calls = self.sequence_calls.lock().unwrap();
+        for file_name in changed {
+            let prefix = format!("{}#", file_name);
+            sequence_calls.retain(|key, _| !key.starts_with(&prefix));
+        }
+    }
+
+    /// Replaces the loaded route files and drops the runtime routes and the response sequence counters.
+    ///
+    /// # Arguments
+    ///
+    /// * `loaded_files` - The route files read again from the search paths.
+    pub fn reset(&self, loaded_files: Vec<LoadedFile>) {
+        self.routes
+            .store(Arc::new(RouteTable::build(loaded_files, vec![], None)));
+        self.sequence_calls.lock().unwrap().clear();
+        info!("Routes reset to the route files");
+    }
+
+    /// Adds a route served before the routes of the files, until it's deleted or the routes are reset.
+    ///
+    /// # Arguments
+    ///
+    /// * `route` - The route to add.
+    ///
+    /// # Returns
+    ///
+    /// Returns the identifier of the route.
+    pub fn add_runtime_route(&self, route: RouteConfiguration) -> u64 {
+        let id = self.next_runtime_id.fetch_add(1, Ordering::Relaxed);
+        let route = Arc::new(route);
+        self.update_runtime_routes(|runtime_routes| {
+            runtime_routes.push(RuntimeRoute {
+                id,
+                route: route.clone(),
+            });
+            true
+        });
+        info!("Added runtime route {} for {}", id, route.url);
+        id
+    }
+
+    /// Replaces a route added through the admin API.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the route.
+    /// * `route` - The new route.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if no runtime route has this identifier.
+    pub fn replace_runtime_route(&self, id: u64, route: RouteConfiguration) -> bool {
+        let route = Arc::new(route);
+        let replaced = self.update_runtime_routes(|runtime_routes| {
+            match runtime_routes.iter_mut().find(|runtime| runtime.id == id) {
+                Some(runtime) => {
+                    runtime.route = route.clone();
+                    true
+                }
+                None => false,
+            }
+        });
+        if replaced {
+            self.sequence_calls
+                .lock()
+                .unwrap()
+                .remove(&format!("runtime#{}", id));
+        }
+        replaced
+    }
+
+    /// Deletes a route added through the admin API.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The identifier of the route.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if no runtime route has this identifier.
+    pub fn remove_runtime_route(&self, id: u64) -> bool {
+        let removed = self.update_runtime_routes(|runtime_routes| {
+            let count = runtime_routes.len();
+            runtime_routes.retain(|runtime| runtime.id != id);
+            runtime_routes.len() != count
+        });
+        if removed {
+            self.sequence_calls
+                .lock()
+                .unwrap()
+                .remove(&format!("runtime#{}", id));
+        }
+        removed
+    }
+
+    /// Swaps in a snapshot with the runtime routes changed by `update`, unless it returns `false`.
+    fn update_runtime_routes(&self, update: impl Fn(&mut Vec<RuntimeRoute>) -> bool) -> bool {
+        let mut updated = false;
+        self.routes.rcu(|current| {
+            let mut runtime_routes = current.runtime_routes.clone();
+            updated = update(&mut runtime_routes);
+            if !updated {
+                return current.clone();
+            }
+            Arc::new(RouteTable::build(
+                current.loaded_files.clone(),
+                runtime_routes,
+                Some(current),
+            ))
+        });
+        updated
+    }
+
+    /// Enables or disables a route. Disabled routes are skipped when matching requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The cache key of the route, like `mocks/users.json#0` or `runtime#1`.
+    /// * `enabled` - Whether the route answers requests.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` if no route has this key.
+    pub fn set_enabled(&self, key: &str, enabled: bool) -> bool {
+        let mut found = false;
+        self.routes.rcu(|current| {
+            found = current
+                .map
+                .values()
+                .flatten()
+                .any(|config| config.cache_key() == key);
+            if !found || current.disabled.contains(key) != enabled {
+                return current.clone();
+            }
+            let mut table = RouteTable::build(
+                current.loaded_files.clone(),
+                current.runtime_routes.clone(),
+                Some(current),
+            );
+            match enabled {
+                true => table.disabled.remove(key),
+                false => table.disabled.insert(key.to_string()),
+            };
+            Arc::new(table)
+        });
+        if found {
+            info!(
+                "{} route {}",
+                if enabled { "Enabled" } else { "Disabled" },
+                key
+            );
+        }
+        found
+    }
+
+    /// Records a call to a route and returns how many calls it had before, starting at `0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The cache key of the route configuration that was called.
+    pub fn next_call(&self, key: &str) -> usize {
+        let mut sequence_calls = self.sequence_calls.lock().unwrap();
+        let calls = sequence_calls.entry(key.to_string()).or_insert(0);
+        *calls += 1;
+        *calls - 1
+    }
+}
+
+/// Options controlling how incoming requests are matched against route guards.
+#[derive(Debug, Clone, Default)]
+pub struct MatchOptions {
+    /// Query parameters that are never compared, even if a route requires them.
+    pub ignored_query: Vec<String>,
+    /// Request headers that are never required, even if a route requires them.
+    pub ignored_headers: Vec<String>,
+}
+
+/// Defaults applied to the responses of every route.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseDefaults {
+    /// Headers added to every response. Headers configured by a route take precedence.
+    pub headers: HashMap<String, String>,
+    /// Delay applied to responses that don't configure their own.
+    pub delay_ms: Option<u64>,
+    /// Response served when no route answers a request, instead of a `501 Not Implemented`.
+    pub fallback: Option<Response>,
 }
 
 /// Represents the configuration for handling incoming requests.
@@ -41,6 +485,10 @@ impl AppState {
 pub struct RequestHandlingConfig {
     /// The type of response file associated with the request configuration.
     pub response_file_type: ResponseFileType,
+    /// The position of the route within its file. Files holding a single route always use `0`.
+    pub index: usize,
+    /// The route, as loaded with its defaults applied, shared with the loaded file.
+    pub route: Arc<RouteConfiguration>,
 }
 
 impl RequestHandlingConfig {
@@ -49,12 +497,30 @@ impl RequestHandlingConfig {
     /// # Arguments
     ///
     /// * `response_file_type` - The type of response file associated with the request configuration.
+    /// * `index` - The position of the route within its file.
+    /// * `route` - The route, as loaded.
     ///
     /// # Returns
     ///
     /// Returns a new `RequestHandlingConfig` instance with the specified response file type.
-    pub fn new(response_file_type: ResponseFileType) -> Self {
-        Self { response_file_type }
+    pub fn new(
+        response_file_type: ResponseFileType,
+        index: usize,
+        route: Arc<RouteConfiguration>,
+    ) -> Self {
+        Self {
+            response_file_type,
+            index,
+            route,
+        }
+    }
+
+    /// Returns the key under which the route configuration is cached.
+    pub fn cache_key(&self) -> String {
+        match self.response_file_type.file_name() {
+            Some(file_name) => format!("{}#{}", file_name, self.index),
+            None => format!("runtime#{}", self.index),
+        }
     }
 }
 
@@ -67,6 +533,130 @@ pub enum ResponseFileType {
     Json(String),
     /// Represents a YAML response with the provided file name.
     Yaml(String),
-    /// Represents a static response.
-    StaticResponse,
+    /// Represents a TOML response with the provided file name.
+    Toml(String),
+    /// Represents a JSON5 or JSONC response with the provided file name.
+    Json5(String),
+    /// Represents a Postman v2.1 collection with the provided file name.
+    Postman(String),
+    /// Represents an HTTP Archive (HAR) with the provided file name.
+    Har(String),
+    /// Represents WireMock stub mappings with the provided file name.
+    WireMock(String),
+    /// Represents a Mockoon environment with the provided file name.
+    Mockoon(String),
+    /// Represents a route added at runtime through the admin API, held in memory.
+    StaticResponse(Arc<RouteConfiguration>),
+}
+
+impl ResponseFileType {
+    /// Returns the name of the file backing the response, if any.
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            ResponseFileType::Json(file_name)
+            | ResponseFileType::Yaml(file_name)
+            | ResponseFileType::Toml(file_name)
+            | ResponseFileType::Json5(file_name)
+            | ResponseFileType::Postman(file_name)
+            | ResponseFileType::Har(file_name)
+            | ResponseFileType::WireMock(file_name)
+            | ResponseFileType::Mockoon(file_name) => Some(file_name),
+            ResponseFileType::StaticResponse(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::request::Responses;
+    use serde_json::{json, Value};
+
+    fn loaded_file(file_name: &str, url: &str, body: Value) -> LoadedFile {
+        LoadedFile {
+            response_file_type: ResponseFileType::Json(file_name.to_string()),
+            routes: vec![Arc::new(RouteConfiguration {
+                name: None,
+                group: None,
+                method: None,
+                url: url.to_string(),
+                headers: None,
+                query: None,
+                body: None,
+                response: Responses::Single(Response {
+                    headers: None,
+                    body,
+                    status_code: None,
+                    delay_ms: None,
+                    raw_body: false,
+                }),
+            })],
+            error: None,
+            dependencies: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_reload() {
+        let accounts = loaded_file("accounts.json", "accounts", json!([]));
+        let users = loaded_file("users.json", "users", json!([]));
+        let state = AppState::new(
+            vec![accounts.clone(), users.clone()],
+            None,
+            MatchOptions::default(),
+            vec![],
+            ScanOptions::default(),
+            ResponseDefaults::default(),
+        );
+        state.next_call("accounts.json#0");
+        state.next_call("users.json#0");
+
+        state.reload(vec![
+            accounts.clone(),
+            loaded_file("users.json", "users/v2", json!(["alice"])),
+        ]);
+
+        let routes = state.routes.load();
+        assert!(routes.map.contains_key("accounts"));
+        assert_eq!(routes.matching("users/v2").count(), 1);
+        assert_eq!(routes.matching("users").count(), 0);
+        let (_, configs) = routes.matching("users/v2").next().unwrap();
+        assert_eq!(
+            configs[0].route.response.get(0).unwrap().body,
+            json!(["alice"])
+        );
+        let sequence_calls = state.sequence_calls.lock().unwrap();
+        assert!(sequence_calls.contains_key("accounts.json#0"));
+        assert!(!sequence_calls.contains_key("users.json#0"));
+    }
+
+    #[test]
+    fn test_runtime_routes() {
+        let users = loaded_file("users.json", "users/{id}", json!("file"));
+        let state = AppState::new(
+            vec![users.clone()],
+            None,
+            MatchOptions::default(),
+            vec![],
+            ScanOptions::default(),
+            ResponseDefaults::default(),
+        );
+        let runtime = loaded_file("", "users/1", json!("runtime"))
+            .routes
+            .remove(0);
+        let id = state.add_runtime_route((*runtime).clone());
+
+        state.reload(vec![users.clone()]);
+        let routes = state.routes.load_full();
+        let (url, _) = routes.matching("users/1").next().unwrap();
+        assert_eq!(url, "users/1");
+        assert_eq!(routes.matching("users/2").count(), 1);
+
+        assert!(state.remove_runtime_route(id));
+        assert!(!state.remove_runtime_route(id));
+        state.add_runtime_route((*loaded_file("", "extra", json!(1)).routes[0]).clone());
+        state.reset(vec![users]);
+        assert!(state.routes.load().runtime_routes.is_empty());
+    }
 }
@@ -0,0 +1,205 @@
+use log::info;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair, KeyUsagePurpose, SerialNumber,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::{Duration, OffsetDateTime};
+
+/// The file the certificate of the generated certificate authority is written to.
+pub const CA_CERT_FILE: &str = "moker-ca.pem";
+
+/// The file the private key of the generated certificate authority is written to.
+pub const CA_KEY_FILE: &str = "moker-ca-key.pem";
+
+/// The common name of the generated certificate authority, shown by the devices that trust it.
+const CA_NAME: &str = "moker development CA";
+
+/// How long server certificates are valid. Apple devices reject server certificates valid for more than 398 days.
+const SERVER_VALIDITY_DAYS: i64 = 397;
+
+/// How long the certificate authority is valid.
+const CA_VALIDITY_DAYS: i64 = 3650;
+
+/// Generates a server certificate for the given host names, signed by a development certificate authority.
+///
+/// The certificate authority is read from `ca_dir` if it holds one, so devices trusting it keep trusting the
+/// certificates of the next runs. Otherwise a new one is generated, and written to `ca_dir` if given.
+///
+/// # Arguments
+///
+/// * `hostnames` - The DNS names and IP addresses the certificate is valid for.
+/// * `ca_dir` - The directory holding the certificate authority.
+///
+/// # Returns
+///
+/// Returns the certificate chain, server certificate first, with the private key of the server certificate,
+/// or an error if the certificate authority can't be read or written.
+pub fn generate(
+    hostnames: &[String],
+    ca_dir: Option<&Path>,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), Box<dyn std::error::Error>> {
+    let (ca, ca_key, ca_der) = match ca_dir {
+        Some(ca_dir) => load_or_create_ca(ca_dir)?,
+        None => {
+            let ca_key = KeyPair::generate()?;
+            let ca = ca_params().self_signed(&ca_key)?;
+            let ca_der = ca.der().clone();
+            (ca, ca_key, ca_der)
+        }
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let mut params = CertificateParams::new(hostnames.to_vec())?;
+    params.distinguished_name.push(
+        DnType::CommonName,
+        hostnames.first().map_or("moker", String::as_str),
+    );
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + Duration::days(SERVER_VALIDITY_DAYS);
+    params.serial_number = Some(serial_number());
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.use_authority_key_identifier_extension = true;
+
+    let key = KeyPair::generate()?;
+    let certificate = params.signed_by(&key, &ca, &ca_key)?;
+    info!(
+        "Generated a certificate for {} signed by the {}",
+        hostnames.join(", "),
+        CA_NAME
+    );
+    Ok((
+        vec![certificate.der().clone(), ca_der],
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+    ))
+}
+
+/// The parameters of the certificate authority.
+///
+/// Their subject never changes, so a certificate authority read from the disk can sign certificates with its key alone.
+fn ca_params() -> CertificateParams {
+    let now = OffsetDateTime::now_utc();
+    let mut params = CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "moker");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + Duration::days(CA_VALIDITY_DAYS);
+    params.serial_number = Some(serial_number());
+    params
+}
+
+/// Reads the certificate authority of a directory, or generates one and writes it there.
+///
+/// # Returns
+///
+/// Returns the certificate authority used to sign, its key, and its certificate as written on the disk.
+fn load_or_create_ca(
+    ca_dir: &Path,
+) -> Result<(Certificate, KeyPair, CertificateDer<'static>), Box<dyn std::error::Error>> {
+    let cert_path = ca_dir.join(CA_CERT_FILE);
+    let key_path = ca_dir.join(CA_KEY_FILE);
+
+    if cert_path.exists() || key_path.exists() {
+        let ca_key = KeyPair::from_pem(
+            &fs::read_to_string(&key_path)
+                .map_err(|err| format!("unable to read {}: {}", key_path.display(), err))?,
+        )?;
+        let ca_der = rustls_pemfile::certs(&mut fs::read(&cert_path)?.as_slice())
+            .next()
+            .ok_or_else(|| format!("no certificate found in {}", cert_path.display()))??;
+        let ca = ca_params().self_signed(&ca_key)?;
+        info!("Using the certificate authority of {}", cert_path.display());
+        return Ok((ca, ca_key, ca_der));
+    }
+
+    let ca_key = KeyPair::generate()?;
+    let ca = ca_params().self_signed(&ca_key)?;
+    fs::create_dir_all(ca_dir)?;
+    fs::write(&cert_path, ca.pem())?;
+    write_private(&key_path, &ca_key.serialize_pem())?;
+    info!(
+        "Wrote the certificate authority to {}, install it on the devices that should trust moker",
+        cert_path.display()
+    );
+    let ca_der = ca.der().clone();
+    Ok((ca, ca_key, ca_der))
+}
+
+/// Writes a private key, readable by its owner only on Unix.
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(content.as_bytes())
+    }
+    #[cfg(not(unix))]
+    fs::write(path, content)
+}
+
+/// Returns a serial number that differs between runs, as browsers reject two certificates with the same issuer
+/// and serial number.
+fn serial_number() -> SerialNumber {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    SerialNumber::from(nanos as u64)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_generate_reuses_ca() {
+        let directory = std::env::temp_dir().join(format!("moker_ca_{}", std::process::id()));
+        _ = fs::remove_dir_all(&directory);
+        let hostnames = vec![String::from("localhost"), String::from("127.0.0.1")];
+
+        let (chain, _) = generate(&hostnames, Some(&directory)).unwrap();
+        let ca = fs::read(directory.join(CA_CERT_FILE)).unwrap();
+        assert!(directory.join(CA_KEY_FILE).is_file());
+        assert_eq!(chain.len(), 2);
+
+        let (next_chain, key) = generate(&hostnames, Some(&directory)).unwrap();
+        assert_eq!(fs::read(directory.join(CA_CERT_FILE)).unwrap(), ca);
+        assert_eq!(next_chain[1], chain[1]);
+        assert_ne!(next_chain[0], chain[0]);
+
+        // The key matches the server certificate
+        rustls::ServerConfig::builder_with_provider(std::sync::Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(next_chain, key)
+        .unwrap();
+        _ = fs::remove_dir_all(&directory);
+    }
+}
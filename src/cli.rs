@@ -1,4 +1,15 @@
-use clap::Parser;
+use crate::file_reader::SearchPath;
+use crate::file_watcher::WatchMode;
+use crate::listen::ListenAddress;
+use crate::replay::Timing;
+use crate::request_log::{parse_duration, parse_size};
+use clap::{Parser, Subcommand};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The ports moker accepts to listen on, from the command line or the configuration file.
+pub const PORT_RANGE: Range<u16> = 1024..65535;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -6,19 +17,240 @@ pub struct Cli {
     /// network port to use
     ///
     /// This option allows the user to specify the network port to be used by the application.
-    #[arg(short, long, default_value_t = 8080, value_name = "PORT", value_parser=clap::value_parser!(u16).range(1024..65535))]
-    pub port: u16,
+    /// Defaults to the port of the configuration file, or 8080.
+    #[arg(short, long, global = true, value_name = "PORT", value_parser=clap::value_parser!(u16).range(PORT_RANGE.start as i64..PORT_RANGE.end as i64))]
+    pub port: Option<u16>,
+
+    /// Host name or IP address to listen on with the port.
+    ///
+    /// `0.0.0.0` accepts connections from other machines and containers over IPv4, `::` over both IPv4 and IPv6.
+    /// Defaults to the address of the configuration file, or 127.0.0.1.
+    #[arg(long, global = true, value_name = "HOST")]
+    pub host: Option<String>,
+
+    /// Address to listen on instead of the host and port: `HOST:PORT`, `[IPV6]:PORT` or `unix:PATH`.
+    ///
+    /// Can be repeated to listen on several addresses. `unix:/tmp/moker.sock` listens on a Unix domain socket.
+    #[arg(long, value_name = "ADDRESS")]
+    pub listen: Vec<ListenAddress>,
 
-    /// Path to look for configuration files.
+    /// Path to look for configuration files, optionally mounted under a URL prefix.
     ///
     /// This option allows the user to specify a custom search path for configuration files.
-    /// By default, it will look for files in the current directory.
-    #[arg(short, long, default_value_t = String::from("./"), value_name = "SEARCH_PATH")]
-    pub search_path: String,
+    /// `./payments@/payments` serves the routes of `./payments` under `/payments`. Can be repeated.
+    /// By default, it will look for files in the search paths of the configuration file, or in the current directory.
+    #[arg(short, long, global = true, value_name = "SEARCH_PATH[@/PREFIX]")]
+    pub search_path: Vec<SearchPath>,
+
+    /// Deprecated and ignored, routes are no longer cached: they are served from memory since they are loaded.
+    /// Still accepted so existing scripts keep working, it will be removed in a future version.
+    #[arg(short, long, value_name = "CACHE_SIZE")]
+    pub cache: Option<usize>,
+
+    /// Number of requests kept in the request journal.
+    ///
+    /// The journal is queried and verified through the `/__moker/journal` endpoints. `0` disables it.
+    /// Defaults to the size of the configuration file, or 1000.
+    #[arg(long, value_name = "SIZE")]
+    pub journal_size: Option<usize>,
 
-    /// Size of the cache.
+    /// JSON Lines file every request is appended to, with its response, matched route and latency.
     ///
-    /// This option allows you to cache the HttpResponse for routes.
-    #[arg(short, long, default_value_t = 20, value_name = "CACHE_SIZE")]
-    pub cache: usize,
+    /// Defaults to the file of the configuration file. Requests aren't logged to a file otherwise.
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Size after which the request log is rotated, like `10MB`.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub log_max_size: Option<u64>,
+
+    /// Age after which the request log is rotated, like `1h` or `1day`.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub log_rotate_every: Option<Duration>,
+
+    /// Number of rotated request logs to keep.
+    ///
+    /// Defaults to the number of the configuration file, or 5.
+    #[arg(long, value_name = "COUNT")]
+    pub log_keep: Option<usize>,
+
+    /// Header whose value is replaced by `[REDACTED]` in the request log.
+    ///
+    /// `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` are always redacted.
+    /// Can be repeated or given as a comma-separated list.
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    pub redact_header: Vec<String>,
+
+    /// Serve HTTPS, and HTTP/2 to the clients that support it.
+    ///
+    /// Uses the certificate and key of `--tls-cert` and `--tls-key`, or of the configuration file.
+    /// Without them, a certificate is generated for the `--tls-hostname` names, signed by a development
+    /// certificate authority.
+    #[arg(long, global = true)]
+    pub tls: bool,
+
+    /// PEM file of the certificate chain to serve HTTPS with. Implies `--tls`.
+    #[arg(long, global = true, value_name = "FILE", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file of the private key of `--tls-cert`. Implies `--tls`.
+    #[arg(long, global = true, value_name = "FILE", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// DNS name or IP address the generated certificate is valid for.
+    ///
+    /// Defaults to the host names of the configuration file, or to `localhost`, 127.0.0.1, ::1 and the host.
+    /// Can be repeated or given as a comma-separated list. Implies `--tls`.
+    #[arg(long, value_name = "NAME", value_delimiter = ',')]
+    pub tls_hostname: Vec<String>,
+
+    /// Directory the generated certificate authority is read from, or written to if it has none.
+    ///
+    /// Install `moker-ca.pem` on the devices calling moker, so they trust its certificates across restarts.
+    /// Implies `--tls`.
+    #[arg(long, value_name = "DIR")]
+    pub tls_ca_dir: Option<PathBuf>,
+
+    /// Seconds given to in-flight requests to complete on SIGINT or SIGTERM.
+    ///
+    /// Connections still open after this delay are closed. Defaults to the timeout of the configuration file, or 5.
+    #[arg(long, value_name = "SECONDS")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// How changes to the route files are noticed.
+    ///
+    /// Use `poll` for bind mounts and network file systems that don't send change notifications.
+    /// Defaults to the mode of the configuration file, or `native`.
+    #[arg(long, value_enum, value_name = "MODE")]
+    pub watch: Option<WatchMode>,
+
+    /// Milliseconds to wait for changes to settle before reloading the routes.
+    ///
+    /// Defaults to the delay of the configuration file, or 2000.
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(10..))]
+    pub debounce_ms: Option<u64>,
+
+    /// Milliseconds between two scans of the search paths in the `poll` mode.
+    ///
+    /// Defaults to the interval of the configuration file, or 1000.
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(10..))]
+    pub poll_interval_ms: Option<u64>,
+
+    /// Profile whose overlay files patch the route files.
+    ///
+    /// With the `staging` profile, `accounts.staging.json` is applied on top of `accounts.json`.
+    #[arg(long, global = true, value_name = "PROFILE")]
+    pub profile: Option<String>,
+
+    /// Path of the configuration file.
+    ///
+    /// By default, `moker.toml` is loaded from the search path if it exists.
+    /// Command line flags take precedence over the values of the file.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Glob pattern of the files to load, relative to the search path.
+    ///
+    /// When given, only the matching files are loaded. Can be repeated.
+    #[arg(long, global = true, value_name = "GLOB", value_parser = parse_glob)]
+    pub include: Vec<String>,
+
+    /// Gitignore-style pattern of the files and directories to skip.
+    ///
+    /// Applied on top of the `.mokerignore` files found in the search path. Can be repeated.
+    #[arg(long, global = true, value_name = "GLOB", value_parser = parse_glob)]
+    pub exclude: Vec<String>,
+
+    /// Query parameter to ignore when matching requests.
+    ///
+    /// Routes requiring this query parameter, like the ones recorded in HAR files, match whatever its value is.
+    /// Can be repeated or given as a comma-separated list.
+    #[arg(long, global = true, value_name = "NAME", value_delimiter = ',')]
+    pub ignore_query: Vec<String>,
+
+    /// Request header to ignore when matching requests.
+    ///
+    /// Routes requiring this header match requests that don't send it.
+    /// Can be repeated or given as a comma-separated list.
+    #[arg(long, global = true, value_name = "NAME", value_delimiter = ',')]
+    pub ignore_header: Vec<String>,
+
+    /// Command to run instead of starting the mock server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Export the loaded routes into another format.
+    Export {
+        #[command(subcommand)]
+        format: ExportFormat,
+    },
+    /// Import routes from another format into route files.
+    Import {
+        #[command(subcommand)]
+        format: ImportFormat,
+    },
+    /// Send the requests of a request log or HAR file again and report the responses that changed.
+    ///
+    /// Status codes and bodies are compared with the recorded responses, JSON bodies value by value.
+    /// Exits with a non-zero status if any response differs or any request fails.
+    Replay {
+        /// The request log (JSON Lines) or HAR file to replay.
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Base URL the requests are sent to.
+        ///
+        /// Defaults to the moker instance listening on the port of the command line or the configuration file.
+        #[arg(short, long, value_name = "URL")]
+        target: Option<String>,
+
+        /// How the requests are paced.
+        #[arg(long, value_enum, default_value_t = Timing::Fast)]
+        timing: Timing,
+
+        /// Response header to compare as well. Can be repeated or given as a comma-separated list.
+        #[arg(long, value_name = "NAME", value_delimiter = ',')]
+        compare_header: Vec<String>,
+    },
+    /// Check the route files for errors without starting the server.
+    ///
+    /// Every problem is reported with its file, line and column. Exits with a non-zero status if any error is found.
+    Validate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImportFormat {
+    /// Convert the entries of an HTTP Archive (HAR) into route files.
+    Har {
+        /// The HAR file to import.
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Directory to write the route files to.
+        #[arg(short, long, default_value = "./", value_name = "OUTPUT")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportFormat {
+    /// Export the loaded routes as an OpenAPI 3 document.
+    Openapi {
+        /// File to write the document to.
+        ///
+        /// The document is written as YAML when the file ends with `.yaml` or `.yml`, and as JSON otherwise.
+        /// By default, it is printed to the standard output.
+        #[arg(short, long, value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Checks that a command line argument is a valid glob pattern.
+fn parse_glob(value: &str) -> Result<String, String> {
+    globset::Glob::new(value)
+        .map(|_| value.to_string())
+        .map_err(|err| err.to_string())
 }
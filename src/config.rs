@@ -0,0 +1,314 @@
+use crate::certificate;
+use crate::cli::PORT_RANGE;
+use crate::file_watcher::WatchMode;
+use crate::request::Response;
+use actix_cors::Cors;
+use log::info;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The name of the project configuration file, looked up in the search path when `--config` isn't given.
+pub const CONFIG_FILE: &str = "moker.toml";
+
+/// The project configuration read from a `moker.toml` file.
+///
+/// Every setting is optional. Command line flags take precedence over the values of the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Network port to use.
+    pub port: Option<u16>,
+    /// Host name or IP address to listen on with the port, also accepted as `host`.
+    #[serde(alias = "host")]
+    pub bind: Option<String>,
+    /// Addresses to listen on instead of the host and port: `HOST:PORT`, `[IPV6]:PORT` or `unix:PATH`.
+    pub listen: Vec<String>,
+    /// Deprecated and ignored like `--cache`, routes are no longer cached.
+    pub cache: Option<usize>,
+    /// Number of requests kept in the request journal, `0` disables it.
+    pub journal_size: Option<usize>,
+    /// Seconds given to in-flight requests to complete when shutting down.
+    pub shutdown_timeout: Option<u64>,
+    /// How changes to the route files are noticed: `native`, `poll` or `off`.
+    pub watch: Option<WatchMode>,
+    /// Milliseconds to wait for changes to settle before reloading the routes.
+    pub debounce_ms: Option<u64>,
+    /// Milliseconds between two scans of the search paths in the `poll` mode.
+    pub poll_interval_ms: Option<u64>,
+    /// Paths to look for route files, relative to the configuration file.
+    pub search_paths: Vec<String>,
+    /// Glob patterns of the files to load.
+    pub include: Vec<String>,
+    /// Gitignore-style patterns of the files and directories to skip.
+    pub exclude: Vec<String>,
+    /// Query parameters to ignore when matching requests.
+    pub ignore_query: Vec<String>,
+    /// Request headers to ignore when matching requests.
+    pub ignore_header: Vec<String>,
+    /// Profile whose overlay files patch the route files.
+    pub profile: Option<String>,
+    /// Defaults applied to every response.
+    pub defaults: DefaultsConfig,
+    /// Cross-origin resource sharing policy. CORS headers are only sent when this section is present.
+    pub cors: Option<CorsConfig>,
+    /// Response served when no route answers a request.
+    pub fallback: Option<Response>,
+    /// Serves HTTPS, with the given certificate and key or a generated one.
+    pub tls: Option<TlsConfig>,
+    /// File the requests are logged to.
+    pub log: LogConfig,
+}
+
+/// Defaults applied to every response.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultsConfig {
+    /// Headers added to every response, unless the route sets them.
+    pub headers: HashMap<String, String>,
+    /// Delay applied to responses that don't configure their own.
+    pub delay_ms: Option<u64>,
+}
+
+/// File the requests are logged to, as JSON Lines.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// The file, relative to the configuration file. Requests aren't logged to a file without it.
+    pub file: Option<PathBuf>,
+    /// Size after which the file is rotated, like `10MB`.
+    pub max_size: Option<String>,
+    /// Age after which the file is rotated, like `1h` or `1day`.
+    pub rotate_every: Option<String>,
+    /// Number of rotated files to keep.
+    pub keep: Option<usize>,
+    /// Headers whose value is redacted, on top of the authorization and cookie headers.
+    pub redact_headers: Vec<String>,
+}
+
+/// Cross-origin resource sharing policy.
+///
+/// Empty lists allow anything, so an empty `[cors]` section accepts every cross-origin request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the server. `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests.
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests.
+    pub allowed_headers: Vec<String>,
+    /// Response headers exposed to the browser.
+    pub expose_headers: Vec<String>,
+    /// Whether cookies and credentials are accepted.
+    pub allow_credentials: bool,
+    /// How long, in seconds, browsers can cache the result of a preflight request.
+    pub max_age: Option<usize>,
+}
+
+impl CorsConfig {
+    /// Builds the CORS middleware enforcing the policy.
+    pub fn middleware(&self) -> Cors {
+        let mut cors = Cors::default();
+
+        if self.allowed_origins.is_empty() || self.allowed_origins.iter().any(|o| o == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &self.allowed_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
+
+        cors = if self.allowed_methods.is_empty() {
+            cors.allow_any_method()
+        } else {
+            cors.allowed_methods(self.allowed_methods.iter().map(String::as_str))
+        };
+
+        cors = if self.allowed_headers.is_empty() {
+            cors.allow_any_header()
+        } else {
+            cors.allowed_headers(self.allowed_headers.iter().map(String::as_str))
+        };
+
+        cors = if self.expose_headers.is_empty() {
+            cors.expose_any_header()
+        } else {
+            cors.expose_headers(self.expose_headers.iter().map(String::as_str))
+        };
+
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+        cors.max_age(self.max_age)
+    }
+}
+
+/// Certificate and key to serve HTTPS with, as PEM files relative to the configuration file.
+///
+/// Without them, a certificate signed by a development certificate authority is generated for the host names.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// The certificate chain.
+    pub cert: Option<PathBuf>,
+    /// The private key of the certificate.
+    pub key: Option<PathBuf>,
+    /// The DNS names and IP addresses the generated certificate is valid for.
+    pub hostnames: Vec<String>,
+    /// The directory the generated certificate authority is read from, or written to, so devices can trust it.
+    pub ca_dir: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Loads the certificate and the key, or generates them, into a TLS server configuration.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `ServerConfig`, or an error if the files can't be read or don't hold a valid certificate and key.
+    pub fn server_config(&self) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+        let (certs, key): (Vec<CertificateDer>, PrivateKeyDer) = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => (
+                rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))
+                    .collect::<Result<_, _>>()?,
+                rustls_pemfile::private_key(&mut BufReader::new(File::open(key)?))?
+                    .ok_or_else(|| format!("no private key found in {:?}", key))?,
+            ),
+            (None, None) => certificate::generate(&self.hostnames, self.ca_dir.as_deref())?,
+            _ => return Err("both a certificate and its key are needed".into()),
+        };
+
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_single_cert(certs, key)?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Reads a configuration file, resolving the paths it holds against its directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `Config`, or an error if the file can't be read or holds unknown or invalid settings.
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let mut config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        if let Some(port) = config.port.filter(|port| !PORT_RANGE.contains(port)) {
+            return Err(format!(
+                "port {} is not in {}..{}",
+                port, PORT_RANGE.start, PORT_RANGE.end
+            )
+            .into());
+        }
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    /// Finds and reads the configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration file given on the command line, which must exist.
+    /// * `search_path` - The search path given on the command line, where `moker.toml` is looked up otherwise.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configuration, the default one if no file was found, or an error if the file is invalid.
+    pub fn find(
+        config: Option<&Path>,
+        search_path: Option<&str>,
+    ) -> Result<Config, Box<dyn std::error::Error>> {
+        let path = match config {
+            Some(path) => path.to_path_buf(),
+            None => Path::new(search_path.unwrap_or("./")).join(CONFIG_FILE),
+        };
+        if config.is_none() && !path.is_file() {
+            return Ok(Config::default());
+        }
+
+        info!("Loading configuration from {:?}", path);
+        Config::load(&path).map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    /// Makes the search paths, TLS files and request log relative to the directory of the configuration file.
+    fn resolve_paths(&mut self, directory: &Path) {
+        for search_path in self.search_paths.iter_mut() {
+            *search_path = directory.join(&search_path).to_string_lossy().to_string();
+        }
+        if let Some(tls) = self.tls.as_mut() {
+            for path in [&mut tls.cert, &mut tls.key, &mut tls.ca_dir]
+                .into_iter()
+                .flatten()
+            {
+                *path = directory.join(&path);
+            }
+        }
+        if let Some(file) = self.log.file.as_mut() {
+            *file = directory.join(&file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_config_resolve_paths() {
+        let mut config: Config = toml::from_str(
+            r#"
+            port = 9000
+            search_paths = ["mocks"]
+
+            [defaults]
+            delay_ms = 100
+            headers = { "x-powered-by" = "moker" }
+
+            [fallback]
+            status_code = 404
+            body = { error = "not found" }
+
+            [tls]
+            cert = "certs/cert.pem"
+            key = "certs/key.pem"
+
+            [log]
+            file = "logs/requests.jsonl"
+            max_size = "10MB"
+            "#,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("project"));
+
+        assert_eq!(config.port, Some(9000));
+        assert_eq!(config.search_paths, vec!["project/mocks"]);
+        assert_eq!(config.defaults.delay_ms, Some(100));
+        assert_eq!(config.fallback.unwrap().status_code, Some(404));
+        assert_eq!(
+            config.tls.unwrap().cert,
+            Some(PathBuf::from("project/certs/cert.pem"))
+        );
+        assert_eq!(
+            config.log.file,
+            Some(PathBuf::from("project/logs/requests.jsonl"))
+        );
+        assert!(toml::from_str::<Config>("prot = 9000").is_err());
+
+        let path = std::env::temp_dir().join(format!("moker_port_{}.toml", std::process::id()));
+        fs::write(&path, "port = 80").unwrap();
+        assert!(Config::load(&path).is_err());
+        _ = fs::remove_file(&path);
+    }
+}
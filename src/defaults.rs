@@ -0,0 +1,255 @@
+use crate::profile;
+use crate::request::RouteConfiguration;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The names of the files holding the defaults of a directory, by order of precedence.
+pub const DEFAULTS_FILES: [&str; 4] = [
+    "_defaults.yaml",
+    "_defaults.yml",
+    "_defaults.json",
+    "_defaults.toml",
+];
+
+/// Defaults inherited by every route file of a directory and its subdirectories.
+///
+/// Routes keep their own values: headers are merged with the route headers taking precedence,
+/// and the status code and delay are only used by responses that don't set them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteDefaults {
+    /// Prefix added to the URL of every route. Prefixes of nested directories are appended to their parent's.
+    pub url_prefix: Option<String>,
+    /// Request headers required by every route.
+    pub headers: HashMap<String, String>,
+    /// Defaults of every response.
+    pub response: DefaultResponse,
+}
+
+/// Defaults of every response of a directory.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultResponse {
+    pub headers: HashMap<String, String>,
+    pub status_code: Option<i32>,
+    pub delay_ms: Option<u64>,
+}
+
+impl RouteDefaults {
+    /// Applies the defaults to a route read from a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `route` - The route to complete.
+    pub fn apply(&self, route: &mut RouteConfiguration) {
+        if let Some(prefix) = &self.url_prefix {
+            route.url = join_urls(prefix, &route.url);
+        }
+
+        if !self.headers.is_empty() {
+            route.headers = Some(merge_headers(&self.headers, route.headers.take()));
+        }
+
+        for response in route.response.iter_mut() {
+            if !self.response.headers.is_empty() {
+                response.headers = Some(merge_headers(
+                    &self.response.headers,
+                    response.headers.take(),
+                ));
+            }
+            response.status_code = response.status_code.or(self.response.status_code);
+            response.delay_ms = response.delay_ms.or(self.response.delay_ms);
+        }
+    }
+
+    /// Merges the defaults of a subdirectory into the ones of its parent.
+    fn merge(&mut self, child: RouteDefaults) {
+        self.url_prefix = match (self.url_prefix.take(), child.url_prefix) {
+            (Some(parent), Some(child)) => Some(join_urls(&parent, &child)),
+            (parent, child) => child.or(parent),
+        };
+        self.headers.extend(child.headers);
+        self.response.headers.extend(child.response.headers);
+        self.response.status_code = child.response.status_code.or(self.response.status_code);
+        self.response.delay_ms = child.response.delay_ms.or(self.response.delay_ms);
+    }
+}
+
+/// Resolves the defaults of route files, reading the defaults file of each directory once.
+pub struct DirectoryDefaults {
+    /// The search path, the defaults of its parent directories are ignored.
+    root: PathBuf,
+    /// The prefix the search path is mounted under, added before the prefixes of the defaults files.
+    mount: Option<String>,
+    /// The merged defaults of the directories read so far.
+    resolved: HashMap<PathBuf, RouteDefaults>,
+    /// The defaults files that couldn't be read, with the reason.
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+impl DirectoryDefaults {
+    /// Creates a resolver for the route files of a search path.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The search path.
+    /// * `mount` - The URL prefix the routes of the search path are served under.
+    pub fn new<P: AsRef<Path>>(root: P, mount: Option<String>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            mount,
+            resolved: HashMap::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Returns the defaults of a route file, merged from the search path down to the directory of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the route file.
+    pub fn for_file(&mut self, path: &Path) -> RouteDefaults {
+        match path.parent() {
+            Some(directory) => self.for_directory(directory),
+            None => RouteDefaults::default(),
+        }
+    }
+
+    fn for_directory(&mut self, directory: &Path) -> RouteDefaults {
+        if let Some(defaults) = self.resolved.get(directory) {
+            return defaults.clone();
+        }
+
+        let mut defaults = match directory.parent() {
+            Some(parent) if directory != self.root && parent.starts_with(&self.root) => {
+                self.for_directory(parent)
+            }
+            _ => RouteDefaults {
+                url_prefix: self.mount.clone(),
+                ..RouteDefaults::default()
+            },
+        };
+        match read_defaults(directory) {
+            Ok(Some(own)) => defaults.merge(own),
+            Ok(None) => {}
+            Err((path, err)) => self.errors.push((path, err)),
+        }
+
+        self.resolved
+            .insert(directory.to_path_buf(), defaults.clone());
+        defaults
+    }
+}
+
+/// Reads the defaults file of a directory, if it has one.
+fn read_defaults(directory: &Path) -> Result<Option<RouteDefaults>, (PathBuf, String)> {
+    let Some(path) = DEFAULTS_FILES
+        .iter()
+        .map(|file_name| directory.join(file_name))
+        .find(|path| path.is_file())
+    else {
+        return Ok(None);
+    };
+
+    let content = fs::read_to_string(&path).map_err(|err| (path.clone(), err.to_string()))?;
+    let value: Result<Value, String> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(|err| err.to_string()),
+        Some("toml") => toml::from_str(&content).map_err(|err| err.message().to_string()),
+        _ => serde_yaml::from_str(&content).map_err(|err| err.to_string()),
+    };
+    let defaults = value.and_then(|mut value| {
+        profile::interpolate(&mut value).map_err(|err| err.to_string())?;
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    });
+    defaults.map(Some).map_err(|err| (path, err))
+}
+
+/// Merges default headers with the headers of a route, which replace the defaults of the same name whatever their case.
+fn merge_headers(
+    defaults: &HashMap<String, String>,
+    headers: Option<HashMap<String, String>>,
+) -> HashMap<String, String> {
+    let headers = headers.unwrap_or_default();
+    let mut merged: HashMap<String, String> = defaults
+        .iter()
+        .filter(|(name, _)| !headers.keys().any(|own| own.eq_ignore_ascii_case(name)))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    merged.extend(headers);
+    merged
+}
+
+/// Joins two URL fragments with a single slash.
+fn join_urls(prefix: &str, url: &str) -> String {
+    [prefix.trim_matches('/'), url.trim_matches('/')]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::request::{Response, Responses};
+    use serde_json::json;
+
+    #[test]
+    fn test_route_defaults_apply() {
+        let mut defaults = RouteDefaults {
+            url_prefix: Some(String::from("api")),
+            headers: HashMap::from([(String::from("authorization"), String::new())]),
+            response: DefaultResponse {
+                headers: HashMap::from([
+                    (
+                        String::from("Content-Type"),
+                        String::from("application/json"),
+                    ),
+                    (String::from("X-Env"), String::from("dev")),
+                ]),
+                status_code: Some(200),
+                delay_ms: Some(10),
+            },
+        };
+        defaults.merge(RouteDefaults {
+            url_prefix: Some(String::from("/v1/")),
+            ..RouteDefaults::default()
+        });
+
+        let mut route = RouteConfiguration {
+            name: None,
+            group: None,
+            method: None,
+            url: String::from("users/{id}"),
+            headers: None,
+            query: None,
+            body: None,
+            response: Responses::Single(Response {
+                headers: Some(HashMap::from([(
+                    String::from("x-env"),
+                    String::from("staging"),
+                )])),
+                body: json!({}),
+                status_code: Some(404),
+                delay_ms: None,
+                raw_body: false,
+            }),
+        };
+        defaults.apply(&mut route);
+
+        assert_eq!(route.url, "api/v1/users/{id}");
+        assert!(route.headers.unwrap().contains_key("authorization"));
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(404));
+        assert_eq!(response.delay_ms, Some(10));
+        let headers = response.headers.as_ref().unwrap();
+        assert_eq!(headers["x-env"], "staging");
+        assert!(!headers.contains_key("X-Env"));
+        assert_eq!(headers["Content-Type"], "application/json");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::app_state::{AppState, RequestHandlingConfig, RouteTable};
+use crate::request::RouteConfiguration;
+use crate::request_handler::guard_mismatches;
+use actix_web::http::header::ACCEPT;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+/// The number of candidate routes listed for an unmatched request.
+const MAX_CANDIDATES: usize = 5;
+
+/// Explains why no route answered a request, listing the closest routes.
+#[derive(Debug, Serialize)]
+pub struct NearMissReport {
+    /// The message of the error, as returned before the candidates were listed.
+    pub error: String,
+    /// The method of the request.
+    pub method: String,
+    /// The path of the request.
+    pub path: String,
+    /// The closest routes, closest first.
+    pub candidates: Vec<Candidate>,
+}
+
+/// A route close to an unmatched request, with the reasons it didn't answer it.
+#[derive(Debug, Serialize)]
+pub struct Candidate {
+    /// The URL of the route, with its placeholders.
+    pub url: String,
+    /// The methods the route accepts, or `ANY`.
+    pub method: String,
+    /// The file the route comes from, with the position of the route in it, or `runtime#ID`.
+    pub source: String,
+    /// Why the route didn't answer the request.
+    pub reasons: Vec<String>,
+}
+
+/// How close a route is to a request, compared to order the candidates.
+struct Closeness {
+    path_matches: bool,
+    literal_segments: usize,
+}
+
+impl NearMissReport {
+    /// Finds the routes closest to a request that no route answered.
+    ///
+    /// A route is a candidate if its URL matches the request path, or shares at least one fixed path segment with it.
+    /// Candidates whose URL matches come first, then the ones sharing the most fixed segments with the path,
+    /// then the ones with the fewest reasons.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - The message explaining that no route answered.
+    /// * `table` - The routes the request was matched against.
+    /// * `state` - The application state holding the match options.
+    /// * `req` - The unmatched request.
+    /// * `body` - The body of the unmatched request.
+    /// * `path` - The request path, without leading and trailing slashes.
+    pub fn new(
+        error: String,
+        table: &RouteTable,
+        state: &AppState,
+        req: &HttpRequest,
+        body: &[u8],
+        path: &str,
+    ) -> Self {
+        let matching: HashSet<&str> = table.matching(path).map(|(url, _)| url).collect();
+
+        let mut candidates = vec![];
+        for (url, configs) in &table.map {
+            let path_matches = matching.contains(url.as_str());
+            let (reasons, literal_segments) = match path_matches {
+                true => (vec![], 0),
+                false => path_mismatches(url, path),
+            };
+            if !path_matches && literal_segments == 0 {
+                continue;
+            }
+
+            for config in configs {
+                let route = &config.route;
+                let mut reasons = reasons.clone();
+                if table.disabled.contains(&config.cache_key()) {
+                    reasons.push(String::from("route: disabled"));
+                }
+                reasons.extend(
+                    guard_mismatches(route, req, body, &state.match_options)
+                        .iter()
+                        .map(ToString::to_string),
+                );
+                let closeness = Closeness {
+                    path_matches,
+                    literal_segments,
+                };
+                candidates.push((closeness, candidate(url, config, route, reasons)));
+            }
+        }
+
+        candidates.sort_by_key(|(closeness, candidate)| {
+            (
+                !closeness.path_matches,
+                Reverse(closeness.literal_segments),
+                candidate.reasons.len(),
+                candidate.url.clone(),
+                candidate.source.clone(),
+            )
+        });
+        Self {
+            error,
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            candidates: candidates
+                .into_iter()
+                .take(MAX_CANDIDATES)
+                .map(|(_, candidate)| candidate)
+                .collect(),
+        }
+    }
+
+    /// Formats the report as a readable table.
+    pub fn to_text(&self) -> String {
+        if self.candidates.is_empty() {
+            return format!(
+                "{}\n\nNo route is close to {} {}",
+                self.error, self.method, self.path
+            );
+        }
+
+        let rows: Vec<[String; 4]> = self
+            .candidates
+            .iter()
+            .map(|candidate| {
+                [
+                    candidate.url.clone(),
+                    candidate.method.clone(),
+                    candidate.source.clone(),
+                    candidate.reasons.join("; "),
+                ]
+            })
+            .collect();
+        let header = [
+            String::from("URL"),
+            String::from("METHOD"),
+            String::from("SOURCE"),
+            String::from("REASONS"),
+        ];
+        let widths: Vec<usize> = (0..3)
+            .map(|column| {
+                rows.iter()
+                    .chain([&header])
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut text = format!(
+            "{}\n\nClosest routes to {} {}:\n",
+            self.error, self.method, self.path
+        );
+        for row in [&header].into_iter().chain(&rows) {
+            text.push_str(&format!(
+                "  {:<w0$}  {:<w1$}  {:<w2$}  {}\n",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2]
+            ));
+        }
+        text.trim_end().to_string()
+    }
+
+    /// Builds the `501 Not Implemented` response, as JSON if the request accepts it and as a text table otherwise.
+    pub fn into_response(self, req: &HttpRequest) -> HttpResponse {
+        let accepts_json = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json"));
+        match accepts_json {
+            true => HttpResponse::NotImplemented().json(self),
+            false => HttpResponse::NotImplemented()
+                .content_type("text/plain; charset=utf-8")
+                .body(self.to_text()),
+        }
+    }
+}
+
+fn candidate(
+    url: &str,
+    config: &RequestHandlingConfig,
+    route: &RouteConfiguration,
+    reasons: Vec<String>,
+) -> Candidate {
+    let method = match route.methods() {
+        Some(methods) => {
+            let mut methods: Vec<String> = methods.into_iter().collect();
+            methods.sort();
+            methods.join(", ")
+        }
+        None => String::from("ANY"),
+    };
+    Candidate {
+        url: url.to_string(),
+        method,
+        source: config.cache_key(),
+        reasons,
+    }
+}
+
+/// Compares the segments of a route URL with the ones of a request path that it doesn't match.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the route, with its placeholders.
+/// * `path` - The request path, without leading and trailing slashes.
+///
+/// # Returns
+///
+/// Returns the reasons the path doesn't match, and the number of fixed segments of the URL found in the path.
+fn path_mismatches(url: &str, path: &str) -> (Vec<String>, usize) {
+    let url_segments: Vec<&str> = url.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+
+    let mut reasons = vec![];
+    if url_segments.len() != path_segments.len() {
+        reasons.push(format!(
+            "path: expected {} segment(s), got {}",
+            url_segments.len(),
+            path_segments.len()
+        ));
+    }
+
+    let mut literal_segments = 0;
+    for (index, (expected, actual)) in url_segments.iter().zip(&path_segments).enumerate() {
+        let is_placeholder = expected.starts_with('{') && expected.ends_with('}');
+        if is_placeholder {
+            // Placeholders match a single word, see `generate_regex_from_route`
+            let is_word =
+                !actual.is_empty() && actual.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if !is_word {
+                reasons.push(format!(
+                    "path segment {}: '{}' doesn't match {}",
+                    index + 1,
+                    actual,
+                    expected
+                ));
+            }
+        } else if expected == actual {
+            literal_segments += 1;
+        } else {
+            reasons.push(format!(
+                "path segment {}: expected '{}', got '{}'",
+                index + 1,
+                expected,
+                actual
+            ));
+        }
+    }
+    (reasons, literal_segments)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_path_mismatches() {
+        assert_eq!(
+            path_mismatches("v1/orders/{id}", "v1/order/1"),
+            (
+                vec![String::from(
+                    "path segment 2: expected 'orders', got 'order'"
+                )],
+                1
+            )
+        );
+        assert_eq!(
+            path_mismatches("v1/orders/{id}", "v1/orders"),
+            (vec![String::from("path: expected 3 segment(s), got 2")], 2)
+        );
+        assert_eq!(
+            path_mismatches("orders/{id}", "orders/a-1").0,
+            vec![String::from("path segment 2: 'a-1' doesn't match {id}")]
+        );
+        assert_eq!(path_mismatches("users", "orders").1, 0);
+    }
+}
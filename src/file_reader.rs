@@ -1,87 +1,847 @@
-use serde_yaml::Value;
-
-use crate::request::RouteConfiguration;
-use std::fs::{self, File};
-use std::io::BufReader;
+use crate::app_state::{MatchOptions, ResponseFileType};
+use crate::har::{har_to_routes, Har};
+use crate::mockoon::{is_mockoon_environment, mockoon_to_routes};
+use crate::postman::{collection_to_routes, is_postman_collection, Collection};
+use crate::profile;
+use crate::refs;
+use crate::request::{Responses, RouteConfiguration};
+use crate::wiremock::{is_wiremock_mapping, wiremock_to_routes};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use log::{info, warn};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Reads a JSON file and deserializes it into an RouteConfiguration.
 ///
 /// # Arguments
 ///
-/// * `file` - A `File` object representing the JSON file to be read.
+/// * `path` - The path of the JSON file to be read.
+/// * `document` - The content of the file, if it was already parsed by `detect_file_type`.
+/// * `dependencies` - Collects the files the route depends on, see `read_routes`.
 ///
 /// # Returns
 ///
 /// Returns a `Result` containing the deserialized `RouteConfiguration` if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
-pub fn read_json_file(file: File) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
-    let reader = BufReader::new(file);
-    let request = serde_json::from_reader(reader)?;
-    Ok(request)
+pub fn read_json_file(
+    path: &Path,
+    document: Option<Value>,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+    read_route_file(path, document, dependencies, |file| {
+        let reader = BufReader::new(file);
+        Ok(serde_json::from_reader(reader)?)
+    })
 }
 
 /// Reads a YAML file and deserializes it into an RouteConfiguration.
 ///
 /// # Arguments
 ///
-/// * `file` - A `File` object representing the YAML file to be read.
+/// * `path` - The path of the YAML file to be read.
+/// * `dependencies` - Collects the files the route depends on, see `read_routes`.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the deserialized `RouteConfiguration` if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_yaml_file(
+    path: &Path,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+    read_route_file(path, None, dependencies, |file| {
+        let reader = BufReader::new(file);
+        Ok(serde_yaml::from_reader(reader)?)
+    })
+}
+
+/// Reads a TOML file and deserializes it into an RouteConfiguration.
+///
+/// # Arguments
+///
+/// * `path` - The path of the TOML file to be read.
+/// * `dependencies` - Collects the files the route depends on, see `read_routes`.
 ///
 /// # Returns
 ///
 /// Returns a `Result` containing the deserialized `RouteConfiguration` if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
-pub fn read_yaml_file(file: File) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+pub fn read_toml_file(
+    path: &Path,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+    read_route_file(path, None, dependencies, |mut file| {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(toml::from_str(&content)?)
+    })
+}
+
+/// Reads a JSON5 or JSONC file and deserializes it into an RouteConfiguration.
+///
+/// Both formats allow comments and trailing commas, JSON5 also allows unquoted keys, single-quoted strings and more.
+///
+/// # Arguments
+///
+/// * `path` - The path of the JSON5 or JSONC file to be read.
+/// * `dependencies` - Collects the files the route depends on, see `read_routes`.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the deserialized `RouteConfiguration` if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_json5_file(
+    path: &Path,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+    read_route_file(path, None, dependencies, |mut file| {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        Ok(json5::from_str(&content)?)
+    })
+}
+
+/// Reads a route file with the overlay of the selected profile applied, its `$ref` fragments included and its environment variables resolved.
+///
+/// # Arguments
+///
+/// * `path` - The path of the route file.
+/// * `document` - The content of the route file, if it was already parsed. It is read with `parse` otherwise.
+/// * `dependencies` - Collects the overlay of the selected profile, whether it exists or not, and the files referenced.
+/// * `parse` - Parses a file of the format of the route file, used for the overlay as well.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the deserialized `RouteConfiguration` if successful, or a `Box`ed `dyn std::error::Error` if a file can't be parsed or a variable can't be resolved.
+fn read_route_file(
+    path: &Path,
+    document: Option<Value>,
+    dependencies: &mut HashSet<PathBuf>,
+    parse: fn(File) -> Result<Value, Box<dyn std::error::Error>>,
+) -> Result<RouteConfiguration, Box<dyn std::error::Error>> {
+    let mut value = match document {
+        Some(document) => document,
+        None => parse(File::open(path)?)?,
+    };
+    let overlay = profile::overlay_path(path);
+    dependencies.extend(overlay.as_deref().map(refs::canonical));
+    if let Some(overlay) = overlay.filter(|overlay| overlay.is_file()) {
+        let patch = parse(File::open(&overlay)?)
+            .map_err(|err| format!("{}: {}", overlay.display(), err))?;
+        profile::merge_patch(&mut value, patch);
+    }
+    refs::resolve_refs(&mut value, path, dependencies)?;
+    profile::interpolate(&mut value)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Reads a JSON, JSON5, YAML or TOML file into a generic value, picking the format from its extension.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file to be read.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the parsed document if successful, or a `Box`ed `dyn std::error::Error` if the file can't be read or parsed.
+pub fn read_value(path: &Path) -> Result<Value, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => Ok(serde_yaml::from_str(&content)?),
+        Some("toml") => Ok(toml::from_str(&content)?),
+        Some("json5" | "jsonc") => Ok(json5::from_str(&content)?),
+        _ => Ok(serde_json::from_str(&content)?),
+    }
+}
+
+/// Reads a Postman v2.1 collection and converts its saved example responses into route configurations.
+///
+/// # Arguments
+///
+/// * `path` - The path of the Postman collection to be read.
+/// * `document` - The content of the file, if it was already parsed by `detect_file_type`.
+///
+/// # Returns
+///
+/// Returns a `Result` containing one `RouteConfiguration` per saved example if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_postman_file(
+    path: &Path,
+    document: Option<Value>,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
+    let collection: Collection = serde_json::from_value(json_document(path, document)?)?;
+    Ok(collection_to_routes(collection))
+}
+
+/// Reads an HTTP Archive (HAR) and converts its recorded entries into route configurations.
+///
+/// # Arguments
+///
+/// * `file` - A `File` object representing the HAR file to be read.
+/// * `match_options` - The query parameters and headers left out of the routes.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the route configurations if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_har_file(
+    file: File,
+    match_options: &MatchOptions,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
     let reader = BufReader::new(file);
-    let request = serde_yaml::from_reader(reader)?;
-    Ok(request)
+    let har: Har = serde_json::from_reader(reader)?;
+    Ok(har_to_routes(har, match_options))
+}
+
+/// Reads WireMock stub mappings and converts them into route configurations.
+///
+/// # Arguments
+///
+/// * `path` - The path of the mapping file. Response bodies referenced by `bodyFileName` are resolved from it.
+/// * `document` - The content of the file, if it was already parsed by `detect_file_type`.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the route configurations if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_wiremock_file(
+    path: &Path,
+    document: Option<Value>,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
+    Ok(wiremock_to_routes(json_document(path, document)?, path))
+}
+
+/// Reads a Mockoon environment and converts its routes into route configurations.
+///
+/// # Arguments
+///
+/// * `path` - The path of the environment file. Files served by `FILE` responses are resolved from it.
+/// * `document` - The content of the file, if it was already parsed by `detect_file_type`.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the route configurations if successful, or a `Box`ed `dyn std::error::Error` if an error occurs during deserialization.
+pub fn read_mockoon_file(
+    path: &Path,
+    document: Option<Value>,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
+    Ok(mockoon_to_routes(json_document(path, document)?, path))
+}
+
+/// Returns the content of a JSON file, parsing it unless it was already parsed.
+fn json_document(
+    path: &Path,
+    document: Option<Value>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    match document {
+        Some(document) => Ok(document),
+        None => Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?),
+    }
+}
+
+/// Detects the type of a route file from its extension and, for JSON files, from its content.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// Returns the `ResponseFileType` of the file, or `None` if moker doesn't support it. JSON files are parsed to
+/// detect their type, their content is returned with it so that `read_routes` doesn't parse them again. It is
+/// `None` for the other formats, and for JSON files that can't be parsed, whose error is reported when they are read.
+pub fn detect_file_type(path: &Path) -> Option<(ResponseFileType, Option<Value>)> {
+    let file_name = path.to_str()?.to_string();
+    let response_file_type = match path.extension()?.to_str()? {
+        "json" => {
+            let document: Option<Value> = File::open(path)
+                .ok()
+                .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok());
+            let response_file_type = match &document {
+                Some(value) if is_postman_collection(value) => ResponseFileType::Postman(file_name),
+                Some(value) if is_wiremock_mapping(value) => ResponseFileType::WireMock(file_name),
+                Some(value) if is_mockoon_environment(value) => {
+                    ResponseFileType::Mockoon(file_name)
+                }
+                _ => ResponseFileType::Json(file_name),
+            };
+            return Some((response_file_type, document));
+        }
+        "yaml" | "yml" => ResponseFileType::Yaml(file_name),
+        "toml" => ResponseFileType::Toml(file_name),
+        "json5" | "jsonc" => ResponseFileType::Json5(file_name),
+        "har" => ResponseFileType::Har(file_name),
+        _ => return None,
+    };
+    Some((response_file_type, None))
+}
+
+/// Reads every route configuration defined by a route file.
+///
+/// # Arguments
+///
+/// * `response_file_type` - The type and location of the route file.
+/// * `document` - The content of the file, if it was already parsed by `detect_file_type`.
+/// * `match_options` - The query parameters and headers left out of the routes recorded in HAR files.
+/// * `dependencies` - Collects the files whose changes affect the routes, as canonical paths: the overlay of the
+///   selected profile and the files referenced with `$ref`. They are collected even if the file can't be read.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the route configurations in the order they appear in the file, or a `Box`ed `dyn std::error::Error` if the file can't be opened, parsed, or isn't backed by a file at all.
+pub fn read_routes(
+    response_file_type: &ResponseFileType,
+    document: Option<Value>,
+    match_options: &MatchOptions,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
+    match response_file_type {
+        ResponseFileType::Json(file_name) => Ok(vec![read_json_file(
+            Path::new(file_name),
+            document,
+            dependencies,
+        )?]),
+        ResponseFileType::Yaml(file_name) => {
+            Ok(vec![read_yaml_file(Path::new(file_name), dependencies)?])
+        }
+        ResponseFileType::Toml(file_name) => {
+            Ok(vec![read_toml_file(Path::new(file_name), dependencies)?])
+        }
+        ResponseFileType::Json5(file_name) => {
+            Ok(vec![read_json5_file(Path::new(file_name), dependencies)?])
+        }
+        ResponseFileType::Postman(file_name) => {
+            interpolate_routes(read_postman_file(Path::new(file_name), document)?)
+        }
+        // Recordings are replayed as they were captured, their bodies often hold `${` of their own
+        ResponseFileType::Har(file_name) => read_har_file(File::open(file_name)?, match_options),
+        ResponseFileType::WireMock(file_name) => {
+            interpolate_routes(read_wiremock_file(Path::new(file_name), document)?)
+        }
+        ResponseFileType::Mockoon(file_name) => {
+            interpolate_routes(read_mockoon_file(Path::new(file_name), document)?)
+        }
+        ResponseFileType::StaticResponse(route) => Ok(vec![(**route).clone()]),
+    }
+}
+
+/// Resolves the environment variable placeholders of the routes converted from another format.
+///
+/// # Arguments
+///
+/// * `routes` - The converted route configurations.
+///
+/// # Returns
+///
+/// Returns the route configurations with their placeholders resolved, or an error naming a variable that can't be resolved.
+fn interpolate_routes(
+    routes: Vec<RouteConfiguration>,
+) -> Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>> {
+    let mut value = serde_json::to_value(routes)?;
+    profile::interpolate(&mut value)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Returns the response of a route file as written, before its fragments, placeholders, overlay and defaults apply.
+///
+/// This is the value `write_response` replaces.
+///
+/// # Arguments
+///
+/// * `response_file_type` - The type and location of the route file.
+///
+/// # Returns
+///
+/// Returns the `response` of the file, or an error if its response can't be written back, see `write_response`.
+pub fn read_response(
+    response_file_type: &ResponseFileType,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    Ok(writable_document(response_file_type)?["response"].take())
+}
+
+/// Replaces the response of a route file.
+///
+/// The file is written again from its parsed content: the other keys keep their values, `$ref` objects and
+/// placeholders included, but comments and formatting are lost, and the keys of JSON and TOML files are sorted.
+/// Only JSON, YAML and TOML route files can be written, the other formats hold several routes.
+/// Responses that include `$ref` fragments or `${VAR}` placeholders, and files patched by the overlay of the selected
+/// profile, are refused: the new response would replace them with the values they resolved to.
+///
+/// # Arguments
+///
+/// * `response_file_type` - The type and location of the route file.
+/// * `response` - The new response of the route.
+///
+/// # Returns
+///
+/// Returns an error if the file can't be read or written, or if its response can't be written back.
+pub fn write_response(
+    response_file_type: &ResponseFileType,
+    response: &Responses,
+) -> Result<(), Box<dyn std::error::Error>> {
+    writable_document(response_file_type)?;
+    let content = match response_file_type {
+        ResponseFileType::Json(file_name) => {
+            let mut value: Value = serde_json::from_str(&std::fs::read_to_string(file_name)?)?;
+            value["response"] = serde_json::to_value(response)?;
+            serde_json::to_string_pretty(&value)? + "\n"
+        }
+        ResponseFileType::Yaml(file_name) => {
+            let mut value: serde_yaml::Value =
+                serde_yaml::from_str(&std::fs::read_to_string(file_name)?)?;
+            value["response"] = serde_yaml::to_value(response)?;
+            serde_yaml::to_string(&value)?
+        }
+        ResponseFileType::Toml(file_name) => {
+            let mut table: toml::Table = toml::from_str(&std::fs::read_to_string(file_name)?)?;
+            table.insert(String::from("response"), toml::Value::try_from(response)?);
+            toml::to_string_pretty(&table)?
+        }
+        other => {
+            return Err(format!(
+                "responses can only be written to JSON, YAML and TOML route files, not {}",
+                other.file_name().unwrap_or("runtime routes")
+            )
+            .into())
+        }
+    };
+    let file_name = response_file_type.file_name().unwrap_or_default();
+    std::fs::write(file_name, content)?;
+    Ok(())
+}
+
+/// Reads a route file whose response can be written back, or returns why it can't.
+fn writable_document(
+    response_file_type: &ResponseFileType,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let path = match response_file_type {
+        ResponseFileType::Json(file_name)
+        | ResponseFileType::Yaml(file_name)
+        | ResponseFileType::Toml(file_name) => Path::new(file_name),
+        other => {
+            return Err(format!(
+                "responses can only be written to JSON, YAML and TOML route files, not {}",
+                other.file_name().unwrap_or("runtime routes")
+            )
+            .into())
+        }
+    };
+    if let Some(overlay) = profile::overlay_path(path).filter(|overlay| overlay.is_file()) {
+        return Err(format!(
+            "{} is patched by {}, its response can't be written back",
+            path.display(),
+            overlay.display()
+        )
+        .into());
+    }
+    let document = read_value(path)?;
+    if needs_resolving(&document["response"]) {
+        return Err(format!(
+            "the response of {} uses $ref fragments or ${{VAR}} placeholders, it can't be written back",
+            path.display()
+        )
+        .into());
+    }
+    Ok(document)
+}
+
+/// Returns `true` if a value holds `$ref` objects or `${VAR}` placeholders, which are replaced when it is loaded.
+fn needs_resolving(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => map.contains_key("$ref") || map.values().any(needs_resolving),
+        Value::Array(values) => values.iter().any(needs_resolving),
+        Value::String(value) => value.contains("${"),
+        _ => false,
+    }
+}
+
+/// The name of the file listing, gitignore-style, the files and directories that are never loaded as routes.
+pub const MOKER_IGNORE_FILE: &str = ".mokerignore";
+
+/// Files and directories that are never loaded as routes, because they belong to the tooling living next to the mocks.
+const DEFAULT_EXCLUDES: [&str; 9] = [
+    "moker.toml",
+    "_defaults.*",
+    "package.json",
+    "package-lock.json",
+    "tsconfig.json",
+    "Cargo.toml",
+    "node_modules/",
+    "target/",
+    "__files/",
+];
+
+/// Options selecting which files of a search path are loaded as routes.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Glob patterns, relative to the search path, a file must match to be loaded. Every file is loaded if empty.
+    pub include: Vec<String>,
+    /// Gitignore-style patterns of files and directories that are never loaded.
+    pub exclude: Vec<String>,
+}
+
+/// A directory of route files, and the URL prefix its routes are served under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchPath {
+    /// The directory holding the route files.
+    pub path: String,
+    /// The prefix added to the URL of every route of the directory, without slashes around it.
+    pub mount: Option<String>,
+}
+
+impl Default for SearchPath {
+    fn default() -> Self {
+        Self {
+            path: String::from("./"),
+            mount: None,
+        }
+    }
+}
+
+impl FromStr for SearchPath {
+    type Err = String;
+
+    /// Parses a search path given as `PATH` or `PATH@/PREFIX`, such as `./payments@/payments`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (path, mount) = match value.rsplit_once("@/") {
+            Some((path, mount)) => (path, Some(mount.trim_matches('/'))),
+            None => (value, None),
+        };
+        if path.is_empty() {
+            return Err(format!("missing directory in search path '{}'", value));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            mount: mount.filter(|mount| !mount.is_empty()).map(String::from),
+        })
+    }
+}
+
+impl fmt::Display for SearchPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.mount {
+            Some(mount) => write!(f, "{}@/{}", self.path, mount),
+            None => write!(f, "{}", self.path),
+        }
+    }
 }
 
 /// Reads files from a directory based on their extension.
 ///
+/// Hidden files and directories, the default exclusions and anything listed in a `.mokerignore` file are skipped.
+/// `.mokerignore` files apply to their own directory and below, like `.gitignore` files.
+///
 /// # Arguments
 ///
 /// * `search_path` - A path to the directory to be searched for configuration files.
 /// * `recursive` - A boolean indicating whether to search recursively within subdirectories.
+/// * `scan_options` - The include and exclude patterns selecting the files to load.
 ///
 /// # Returns
 ///
-/// Returns a vector of `PathBuf` containing the paths of the found configuration files.
+/// Returns a vector of `PathBuf` containing the paths of the found configuration files, sorted by path.
+/// Profile overlays are left out, they are applied when their base file is read.
 ///
 /// # Example
 ///
 /// ```
 /// use std::path::Path;
-/// use crate::request_reader::read_directory;
+/// use crate::file_reader::{read_directory, ScanOptions};
 ///
-/// let files = read_directory(Path::new("./config"), true);
+/// let files = read_directory(Path::new("./config"), true, &ScanOptions::default());
 /// for file in files {
 ///     println!("Found file: {:?}", file);
 /// }
 /// ```
-pub fn read_directory<P: AsRef<Path>>(search_path: P, recursive: bool) -> Vec<PathBuf> {
-    let mut files: Vec<PathBuf> = vec![];
-    find_config_files(search_path.as_ref(), &mut files, recursive);
-    files
-}
+pub fn read_directory<P: AsRef<Path>>(
+    search_path: P,
+    recursive: bool,
+    scan_options: &ScanOptions,
+) -> Vec<PathBuf> {
+    let search_path = search_path.as_ref();
 
-/// Recursively searches for configuration files in a directory and its subdirectories.
-///
-/// # Arguments
-///
-/// * `search_path` - A reference to the current search path.
-/// * `vec` - A mutable reference to a vector to store the found configuration file paths.
-/// * `recursive` - A boolean indicating whether to search recursively within subdirectories.
-fn find_config_files(search_path: &Path, vec: &mut Vec<PathBuf>, recursive: bool) {
-    fs::read_dir(search_path)
-        .unwrap()
-        .filter_map(|dir| dir.ok())
-        .map(|dir_entry| dir_entry.path())
-        .for_each(|path| {
-            if recursive && path.is_dir() {
-                find_config_files(search_path, vec, recursive);
-            } else if path.extension().map_or(false, |ext| {
-                matches!(ext.to_str(), Some("json") | Some("yaml") | Some("yml"))
-            }) {
-                vec.push(path);
+    let (excludes, includes) = match build_filters(search_path, scan_options) {
+        Ok(filters) => filters,
+        Err(err) => {
+            warn!("Invalid include or exclude pattern: {}", err);
+            return vec![];
+        }
+    };
+
+    WalkBuilder::new(search_path)
+        .standard_filters(false)
+        .hidden(true)
+        .add_custom_ignore_filename(MOKER_IGNORE_FILE)
+        .overrides(excludes)
+        .max_depth((!recursive).then_some(1))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            Err(err) => {
+                warn!("Unable to read {:?}: {}", search_path, err);
+                None
+            }
+        })
+        .filter(|path| path.is_file() && is_config_file(path))
+        .filter(|path| match profile::overlay_base(path) {
+            Some(base) => {
+                info!(
+                    "Not loading {:?} as routes, it is the profile overlay of {:?}",
+                    path, base
+                );
+                false
             }
-        });
+            None => true,
+        })
+        .filter(|path| {
+            includes.is_empty() || includes.is_match(path.strip_prefix(search_path).unwrap_or(path))
+        })
+        .collect()
+}
+
+/// Builds the exclude overrides and the include glob set of a search path.
+fn build_filters(
+    search_path: &Path,
+    scan_options: &ScanOptions,
+) -> Result<(Override, GlobSet), Box<dyn std::error::Error>> {
+    let mut excludes = OverrideBuilder::new(search_path);
+    for pattern in DEFAULT_EXCLUDES
+        .into_iter()
+        .chain(scan_options.exclude.iter().map(String::as_str))
+    {
+        excludes.add(&format!("!{}", pattern))?;
+    }
+
+    let mut includes = GlobSetBuilder::new();
+    for pattern in &scan_options.include {
+        includes.add(Glob::new(pattern)?);
+    }
+
+    Ok((excludes.build()?, includes.build()?))
+}
+
+/// Returns `true` if a file has the extension of a supported route file.
+fn is_config_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        matches!(
+            ext.to_str(),
+            Some("json")
+                | Some("json5")
+                | Some("jsonc")
+                | Some("yaml")
+                | Some("yml")
+                | Some("toml")
+                | Some("har")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_directory_recursive() {
+        let files = read_directory("./example", true, &ScanOptions::default());
+
+        assert!(files.contains(&PathBuf::from("./example/json/account_details.json")));
+        assert!(files.contains(&PathBuf::from("./example/yaml/debit_cards.yaml")));
+        assert!(!files.contains(&PathBuf::from("./example/wiremock/__files/user.json")));
+    }
+
+    #[test]
+    fn test_read_directory_include_exclude() {
+        let scan_options = ScanOptions {
+            include: vec![String::from("json/*")],
+            exclude: vec![String::from("credit_*.json")],
+        };
+        let files = read_directory("./example", true, &scan_options);
+
+        assert!(files.contains(&PathBuf::from("./example/json/account_details.json")));
+        assert!(!files.contains(&PathBuf::from("./example/json/credit_cards.json")));
+        assert!(!files.contains(&PathBuf::from("./example/yaml/debit_cards.yaml")));
+    }
+
+    #[test]
+    fn test_read_toml_file() {
+        let route = read_toml_file(
+            Path::new("./example/toml/savings_account.toml"),
+            &mut HashSet::new(),
+        )
+        .unwrap();
+
+        assert_eq!(route.url, "account/v1/savings/{id}");
+        assert_eq!(route.method, Some(serde_json::json!(["GET", "HEAD"])));
+        assert!(route.headers.unwrap().contains_key("api_token"));
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(200));
+        assert_eq!(response.body["balance"], serde_json::json!(5687123.56));
+        assert_eq!(response.body["profiles"][1], "linda_12312");
+    }
+
+    #[test]
+    fn test_read_json5_file() {
+        let path = Path::new("./example/jsonc/loan_details.jsonc");
+        assert!(matches!(
+            detect_file_type(path),
+            Some((ResponseFileType::Json5(_), None))
+        ));
+
+        let route = read_json5_file(path, &mut HashSet::new()).unwrap();
+
+        assert_eq!(route.url, "loans/v1/details/{id}");
+        let response = route.response.get(0).unwrap();
+        assert_eq!(
+            response.headers.as_ref().unwrap()["Content-Type"],
+            "application/json"
+        );
+        assert_eq!(response.body["principal"], 2500000);
+        assert_eq!(response.body["overdue"], true);
+    }
+
+    #[test]
+    fn test_search_path_from_str() {
+        let search_path: SearchPath = "./teams/payments@/payments/v1/".parse().unwrap();
+        assert_eq!(search_path.path, "./teams/payments");
+        assert_eq!(search_path.mount.as_deref(), Some("payments/v1"));
+
+        let search_path: SearchPath = "./mocks@home".parse().unwrap();
+        assert_eq!(search_path.path, "./mocks@home");
+        assert_eq!(search_path.mount, None);
+
+        assert!("@/payments".parse::<SearchPath>().is_err());
+    }
+
+    #[test]
+    fn test_write_response() {
+        let path = std::env::temp_dir().join(format!("moker_write_{}.yaml", std::process::id()));
+        std::fs::write(&path, "url: /users\nmethod: GET\nresponse:\n  body: []\n").unwrap();
+        let response_file_type = ResponseFileType::Yaml(path.to_string_lossy().to_string());
+        let response: Responses =
+            serde_json::from_value(serde_json::json!({ "status_code": 201, "body": ["ada"] }))
+                .unwrap();
+
+        write_response(&response_file_type, &response).unwrap();
+        let route = read_yaml_file(&path, &mut HashSet::new()).unwrap();
+        assert_eq!(route.method, Some(serde_json::json!("GET")));
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(201));
+        assert_eq!(response.body, serde_json::json!(["ada"]));
+
+        let postman = ResponseFileType::Postman(String::from("collection.json"));
+        assert!(write_response(&postman, &route.response).is_err());
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_write_response_as_written() {
+        let directory =
+            std::env::temp_dir().join(format!("moker_write_back_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let write = |file_name: &str, value: Value| {
+            let path = directory.join(file_name);
+            std::fs::write(&path, value.to_string()).unwrap();
+            ResponseFileType::Json(path.to_string_lossy().to_string())
+        };
+        write(
+            "_defaults.json",
+            json!({ "response": { "status_code": 202, "headers": { "x-team": "core" } } }),
+        );
+        write("shared.json", json!({ "token": { "api_token": "1" } }));
+        let users = write(
+            "users.json",
+            json!({ "url": "users", "headers": { "$ref": "shared.json#/token" }, "response": { "body": [] } }),
+        );
+        let admins = write(
+            "admins.json",
+            json!({ "url": "admins", "response": { "body": { "$ref": "shared.json#/token" } } }),
+        );
+
+        assert_eq!(read_response(&users).unwrap(), json!({ "body": [] }));
+        let response: Responses = serde_json::from_value(json!({ "body": ["ada"] })).unwrap();
+        write_response(&users, &response).unwrap();
+
+        let document = read_value(Path::new(users.file_name().unwrap())).unwrap();
+        assert_eq!(document["headers"], json!({ "$ref": "shared.json#/token" }));
+        assert_eq!(document["response"], json!({ "body": ["ada"] }));
+        assert!(read_response(&admins).is_err());
+        assert!(write_response(&admins, &response).is_err());
+
+        let search_paths = [directory.to_string_lossy().parse().unwrap()];
+        let loaded = crate::request_handler::load_route_files(
+            &search_paths,
+            &ScanOptions::default(),
+            &MatchOptions::default(),
+            &[],
+        );
+        let route = loaded
+            .iter()
+            .flat_map(|file| &file.routes)
+            .find(|route| route.url == "users")
+            .unwrap();
+        assert_eq!(route.headers.as_ref().unwrap()["api_token"], "1");
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(202));
+        assert_eq!(response.body, json!(["ada"]));
+        _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_read_routes_interpolation() {
+        std::env::set_var("MOKER_TEST_WIREMOCK_ROLE", "admin");
+        let directory =
+            std::env::temp_dir().join(format!("moker_interpolation_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let wiremock = directory.join("mapping.json");
+        std::fs::write(
+            &wiremock,
+            json!({ "request": { "method": "GET", "urlPath": "/role" },
+                    "response": { "status": 200, "jsonBody": { "role": "${MOKER_TEST_WIREMOCK_ROLE}" } } })
+            .to_string(),
+        )
+        .unwrap();
+        let har = directory.join("recording.har");
+        std::fs::write(
+            &har,
+            json!({ "log": { "entries": [{
+                "request": { "method": "GET", "url": "https://example.com/app.js", "headers": [] },
+                "response": { "status": 200, "headers": [],
+                              "content": { "mimeType": "text/javascript", "text": "`${MOKER_TEST_WIREMOCK_ROLE}`" } }
+            }] } })
+            .to_string(),
+        )
+        .unwrap();
+
+        let (file_type, document) = detect_file_type(&wiremock).unwrap();
+        assert!(matches!(file_type, ResponseFileType::WireMock(_)));
+        assert!(document.is_some());
+        let routes = read_routes(
+            &file_type,
+            document,
+            &MatchOptions::default(),
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            routes[0].response.get(0).unwrap().body,
+            json!({ "role": "admin" })
+        );
+
+        let (file_type, document) = detect_file_type(&har).unwrap();
+        let routes = read_routes(
+            &file_type,
+            document,
+            &MatchOptions::default(),
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            routes[0].response.get(0).unwrap().body,
+            json!("`${MOKER_TEST_WIREMOCK_ROLE}`")
+        );
+        _ = std::fs::remove_dir_all(&directory);
+    }
 }
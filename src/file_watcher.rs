@@ -1,82 +1,365 @@
-use crate::{app_state::AppState, request_handler};
+use crate::app_state::AppState;
+use crate::refs;
+use crate::request_handler;
 use actix_web::web::Data;
-use futures::{channel::mpsc::channel, SinkExt, StreamExt};
-use log::{info, warn};
-use notify_debouncer_full::{new_debouncer, notify::*, DebounceEventResult};
-use std::{path::Path, time::Duration};
+use futures::channel::mpsc::{channel, Sender};
+use futures::channel::oneshot;
+use futures::{FutureExt, SinkExt, StreamExt};
+use log::{error, info, warn};
+use notify_debouncer_full::notify::{
+    self, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use notify_debouncer_full::{new_debouncer_opt, DebounceEventResult, Debouncer, FileIdMap};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How changes to the route files are noticed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchMode {
+    /// Use the notifications of the operating system, falling back to polling if they are unavailable.
+    #[default]
+    Native,
+    /// Scan the search paths periodically, for bind mounts and network file systems that don't send notifications.
+    Poll,
+    /// Don't watch the files. Routes are only reloaded on `SIGHUP`.
+    Off,
+}
+
+/// Options of the file watcher.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// How changes are noticed.
+    pub mode: WatchMode,
+    /// How long to wait for changes to settle before reloading.
+    pub debounce: Duration,
+    /// How often the search paths are scanned in the `poll` mode.
+    pub poll_interval: Duration,
+}
+
+/// The debouncer of the watcher in use, kept alive while watching.
+enum Watching {
+    Native(Debouncer<RecommendedWatcher, FileIdMap>),
+    Poll(Debouncer<PollWatcher, FileIdMap>),
+}
+
+impl Watching {
+    fn stop(self) {
+        match self {
+            Watching::Native(debouncer) => debouncer.stop(),
+            Watching::Poll(debouncer) => debouncer.stop(),
+        }
+    }
+
+    /// Watches the directories of the files referenced from outside the search paths, see `watch_dependencies`.
+    fn watch_dependencies(&mut self, app_state: &AppState, watched: &mut HashSet<PathBuf>) {
+        match self {
+            Watching::Native(debouncer) => watch_dependencies(debouncer, app_state, watched),
+            Watching::Poll(debouncer) => watch_dependencies(debouncer, app_state, watched),
+        }
+    }
+}
 
 /// Asynchronously watches for file changes and updates the application state accordingly.
 ///
-/// This function sets up a file watcher that monitors the specified path for changes (create, modify, or remove events).
-/// When a significant event occurs, it updates the application state with the new configuration.
+/// This function sets up a file watcher that monitors the search paths of the application state for changes (create, modify, or remove events),
+/// as well as the directories of the files they reference with `$ref` or patch with a profile overlay, outside the search paths.
+/// When a significant event occurs, it reads the changed files again, along with the files referencing them, and updates the application state
+/// with the new configuration: routes of deleted files are removed, and the routes of changed files are replaced.
+/// On Unix, a `SIGHUP` forces a full reload.
 ///
 /// # Arguments
 ///
-/// * `path` - A path to the directory or file to be watched.
 /// * `app_state` - A reference to the application state (`AppState`) shared across the application.
+/// * `options` - How changes are noticed, and how long to wait for them to settle.
+/// * `shutdown` - Completes when the server has stopped, which stops the watcher.
 ///
 /// # Example
 ///
 /// ```rust
 /// use crate::AppState;
 /// use actix_web::web::Data;
-/// use std::path::Path;
 ///
 /// #[actix_rt::main]
 /// async fn main() {
 ///     let app_state = Data::new(AppState::default()); // Create application state
+///     let (stop, shutdown) = futures::channel::oneshot::channel();
 ///
-///     // Start watching a directory for changes
-///     file_watcher(Path::new("./config"), app_state).await;
+///     let options = WatchOptions {
+///         mode: WatchMode::Poll,
+///         debounce: Duration::from_secs(2),
+///         poll_interval: Duration::from_secs(1),
+///     };
+///
+///     // Start watching the search paths for changes, until `stop` is used or dropped
+///     file_watcher(app_state, options, shutdown).await;
 /// }
 /// ```
 ///
-/// # Panics
-///
-/// This function may panic if it encounters errors while setting up the file watcher or processing events.
-/// It is advisable to handle errors appropriately in production code.
-pub async fn file_watcher<P: AsRef<Path>>(path: P, app_state: Data<AppState>) {
-    let (mut tx, mut rx) = channel(32);
+/// If watching isn't possible at all, hot reload is disabled and an error is logged, the server keeps running.
+pub async fn file_watcher(
+    app_state: Data<AppState>,
+    options: WatchOptions,
+    shutdown: oneshot::Receiver<()>,
+) {
+    let (tx, mut rx) = channel(32);
+
+    let mut watching = match options.mode {
+        WatchMode::Off => {
+            info!(target: "file_watcher", "File watching is off, send SIGHUP to reload the routes");
+            None
+        }
+        WatchMode::Native => {
+            match start_debouncer(&app_state, &options, tx.clone(), notify::Config::default()) {
+                Ok(debouncer) => Some(Watching::Native(debouncer)),
+                Err(err) => {
+                    warn!(target: "file_watcher", "Unable to watch the search paths natively, polling them instead: {}", err);
+                    start_polling(&app_state, &options, tx.clone())
+                }
+            }
+        }
+        WatchMode::Poll => start_polling(&app_state, &options, tx.clone()),
+    };
 
-    let mut debouncer = new_debouncer(
-        Duration::from_secs(2),
+    let mut dependency_directories = HashSet::new();
+    if let Some(watching) = &mut watching {
+        watching.watch_dependencies(&app_state, &mut dependency_directories);
+    }
+
+    let mut hangup = hangup_signal();
+    let mut shutdown = shutdown.fuse();
+
+    loop {
+        let res = futures::select! {
+            res = rx.next() => match res {
+                Some(res) => res,
+                None => break,
+            },
+            _ = next_hangup(&mut hangup).fuse() => {
+                info!(target: "file_watcher", "SIGHUP received, reloading every route file");
+                reload_routes(&app_state);
+                if let Some(watching) = &mut watching {
+                    watching.watch_dependencies(&app_state, &mut dependency_directories);
+                }
+                continue;
+            },
+            _ = shutdown => break,
+        };
+
+        match res {
+            Ok(events) => {
+                let significant_events: Vec<_> = events
+                    .iter()
+                    .filter(|event| {
+                        matches!(
+                            event.kind,
+                            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                        ) || event.need_rescan()
+                    })
+                    .collect();
+
+                if !significant_events.is_empty() {
+                    info!(target: "file_watcher", "File changed: {:?}", events);
+                    // Events were dropped, the changed files are unknown
+                    if significant_events.iter().any(|event| event.need_rescan()) {
+                        reload_routes(&app_state);
+                    } else {
+                        let changed = significant_events
+                            .iter()
+                            .flat_map(|event| &event.paths)
+                            .map(|path| refs::canonical(path))
+                            .collect();
+                        reload_changed_routes(&app_state, &changed);
+                    }
+                    if let Some(watching) = &mut watching {
+                        watching.watch_dependencies(&app_state, &mut dependency_directories);
+                    }
+                }
+            }
+            Err(e) => warn!("File watcher error: {:?}", e),
+        }
+    }
+
+    // Closing the channel first wakes up the debouncer if it is blocked on a full channel, stopping it joins its thread
+    drop(rx);
+    if let Some(watching) = watching {
+        watching.stop();
+    }
+    // Kept until now so the channel stays open when nothing is watched
+    drop(tx);
+    info!(target: "file_watcher", "File watcher stopped");
+}
+
+/// Starts polling the search paths, logging an error if it isn't possible.
+fn start_polling(
+    app_state: &AppState,
+    options: &WatchOptions,
+    tx: Sender<DebounceEventResult>,
+) -> Option<Watching> {
+    let config = notify::Config::default().with_poll_interval(options.poll_interval);
+    match start_debouncer(app_state, options, tx, config) {
+        Ok(debouncer) => {
+            info!(target: "file_watcher", "Polling the search paths every {:?}", options.poll_interval);
+            Some(Watching::Poll(debouncer))
+        }
+        Err(err) => {
+            error!(target: "file_watcher", "Unable to watch the search paths, hot reload is disabled: {}", err);
+            None
+        }
+    }
+}
+
+/// Creates a debouncer with the watcher `T` and watches every search path with it.
+fn start_debouncer<T: Watcher>(
+    app_state: &AppState,
+    options: &WatchOptions,
+    mut tx: Sender<DebounceEventResult>,
+    config: notify::Config,
+) -> Result<Debouncer<T, FileIdMap>, notify::Error> {
+    let mut debouncer = new_debouncer_opt::<_, T, FileIdMap>(
+        options.debounce,
         None,
         move |res: DebounceEventResult| {
             futures::executor::block_on(async {
                 _ = tx.send(res).await;
             })
         },
-    )
-    .unwrap();
+        FileIdMap::new(),
+        config,
+    )?;
 
-    debouncer
-        .cache()
-        .add_root(path.as_ref(), RecursiveMode::Recursive);
+    for search_path in &app_state.search_paths {
+        let path = Path::new(&search_path.path);
+        debouncer.cache().add_root(path, RecursiveMode::Recursive);
 
-    debouncer
-        .watcher()
-        .watch(path.as_ref(), RecursiveMode::Recursive)
-        .expect("Failed to watch path");
+        debouncer.watcher().watch(path, RecursiveMode::Recursive)?;
+    }
+    Ok(debouncer)
+}
 
-    while let Some(res) = rx.next().await {
-        match res {
-            Ok(events) => {
-                let has_significant_event = events.iter().any(|event| {
-                    matches!(
-                        event.kind,
-                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
-                    )
-                });
-
-                if has_significant_event {
-                    info!(target: "file_watcher", "File changed: {:?}", events);
-                    let search_path = path.as_ref().to_string_lossy().into_owned();
-                    let request_map = request_handler::create_route_map(Some(search_path));
-                    let mut config_map = app_state.config_map.lock().unwrap();
-                    config_map.extend(request_map);
-                }
+/// Watches the directories holding the dependencies of the loaded files that are outside the search paths.
+///
+/// Directories that no longer hold a dependency stop being watched. A directory that can't be watched, for example
+/// because it doesn't exist yet, is tried again after the next reload.
+///
+/// # Arguments
+///
+/// * `debouncer` - The debouncer watching the search paths.
+/// * `app_state` - The application state holding the loaded files.
+/// * `watched` - The dependency directories being watched, updated in place.
+fn watch_dependencies<T: Watcher>(
+    debouncer: &mut Debouncer<T, FileIdMap>,
+    app_state: &AppState,
+    watched: &mut HashSet<PathBuf>,
+) {
+    let search_paths: Vec<PathBuf> = app_state
+        .search_paths
+        .iter()
+        .map(|search_path| refs::canonical(Path::new(&search_path.path)))
+        .collect();
+    let directories: HashSet<PathBuf> = app_state
+        .routes
+        .load()
+        .loaded_files
+        .iter()
+        .flat_map(|file| &file.dependencies)
+        .filter_map(|dependency| dependency.parent())
+        .filter(|directory| {
+            !search_paths
+                .iter()
+                .any(|search_path| directory.starts_with(search_path))
+        })
+        .map(Path::to_path_buf)
+        .collect();
+
+    for directory in watched.difference(&directories) {
+        debouncer.cache().remove_root(directory);
+        _ = debouncer.watcher().unwatch(directory);
+    }
+    watched.retain(|directory| directories.contains(directory));
+
+    for directory in directories {
+        if watched.contains(&directory) {
+            continue;
+        }
+        match debouncer
+            .watcher()
+            .watch(&directory, RecursiveMode::NonRecursive)
+        {
+            Ok(()) => {
+                info!(target: "file_watcher", "Watching {:?}, referenced by the route files", directory);
+                debouncer
+                    .cache()
+                    .add_root(&directory, RecursiveMode::NonRecursive);
+                watched.insert(directory);
+            }
+            Err(err) => {
+                warn!(target: "file_watcher", "Unable to watch {:?}, referenced by the route files: {}", directory, err)
             }
-            Err(e) => warn!("File watcher error: {:?}", e),
         }
     }
 }
+
+/// Reads the route files of every search path again and swaps the routes being served.
+///
+/// # Arguments
+///
+/// * `app_state` - The application state holding the routes.
+pub fn reload_routes(app_state: &AppState) {
+    let loaded_files = request_handler::load_route_files(
+        &app_state.search_paths,
+        &app_state.scan_options,
+        &app_state.match_options,
+        &app_state.routes.load().loaded_files,
+    );
+    app_state.reload(loaded_files);
+}
+
+/// Reads the changed route files again, along with the files depending on them, and swaps the routes being served.
+///
+/// # Arguments
+///
+/// * `app_state` - The application state holding the routes.
+/// * `changed` - The canonical paths of the files that were created, modified or removed.
+pub fn reload_changed_routes(app_state: &AppState, changed: &HashSet<PathBuf>) {
+    let loaded_files = request_handler::reload_changed_files(
+        &app_state.search_paths,
+        &app_state.scan_options,
+        &app_state.match_options,
+        &app_state.routes.load().loaded_files,
+        changed,
+    );
+    app_state.reload(loaded_files);
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type Hangup = ();
+
+/// Listens to `SIGHUP`, on platforms that have it.
+#[cfg(unix)]
+fn hangup_signal() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::hangup())
+        .inspect_err(|err| warn!("Unable to listen to SIGHUP: {}", err))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> Hangup {}
+
+/// Waits for the next `SIGHUP`. Never completes if the signal can't be received.
+async fn next_hangup(hangup: &mut Hangup) {
+    #[cfg(unix)]
+    if let Some(hangup) = hangup {
+        hangup.recv().await;
+        return;
+    }
+    #[cfg(not(unix))]
+    let _ = hangup;
+    futures::future::pending::<()>().await
+}
@@ -0,0 +1,451 @@
+use crate::app_state::MatchOptions;
+use crate::journal::{JournalEntry, JournalResponse};
+use crate::request::{Response, Responses, RouteConfiguration, SERVER_MANAGED_HEADERS};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+/// Request headers recorded by browsers that describe the client or the connection rather than the API call.
+///
+/// They are not turned into header guards, otherwise the recorded requests could only be replayed by a browser.
+const CLIENT_HEADERS: [&str; 17] = [
+    "host",
+    "user-agent",
+    "accept",
+    "accept-encoding",
+    "accept-language",
+    "connection",
+    "content-length",
+    "cookie",
+    "referer",
+    "origin",
+    "cache-control",
+    "pragma",
+    "dnt",
+    "te",
+    "priority",
+    "upgrade-insecure-requests",
+    "if-none-match",
+];
+
+/// An HTTP Archive, limited to the parts moker needs.
+#[derive(Debug, Deserialize)]
+pub struct Har {
+    log: Log,
+}
+
+#[derive(Debug, Deserialize)]
+struct Log {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+/// A recorded request/response exchange.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: Option<String>,
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<NameValue>,
+    #[serde(default)]
+    query_string: Vec<NameValue>,
+    post_data: Option<PostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PostData {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    status: i32,
+    #[serde(default)]
+    headers: Vec<NameValue>,
+    #[serde(default)]
+    content: Content,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    mime_type: Option<String>,
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+/// Converts the entries of an HTTP Archive into route configurations.
+///
+/// Entries sharing the same method, path and query parameters become a single route whose responses are
+/// replayed as a sequence, in the order they were recorded. The recorded host is kept as the route `group`.
+/// Entries without a response (for example blocked or aborted requests) are skipped.
+/// Ignored query parameters and headers are left out of the routes, so entries differing only by them, like a
+/// cache buster, are replayed as a single sequence.
+///
+/// # Arguments
+///
+/// * `har` - The deserialized HTTP Archive.
+/// * `match_options` - The query parameters and headers ignored when matching requests.
+///
+/// # Returns
+///
+/// Returns the route configurations in the order their first entry was recorded.
+pub fn har_to_routes(har: Har, match_options: &MatchOptions) -> Vec<RouteConfiguration> {
+    let mut routes: Vec<(String, RouteConfiguration, Vec<Response>)> = vec![];
+
+    for entry in har.log.entries {
+        if entry.response.status == 0 {
+            warn!(
+                "HAR entry {} {} has no response, skipping",
+                entry.request.method, entry.request.url
+            );
+            continue;
+        }
+
+        let (host, url) = split_url(&entry.request.url);
+        let mut query: Vec<(String, String)> = entry
+            .request
+            .query_string
+            .into_iter()
+            .filter(|param| !match_options.ignored_query.contains(&param.name))
+            .map(|param| (param.name, param.value))
+            .collect();
+        query.sort();
+
+        let key = format!("{} {}?{:?}", entry.request.method, url, query);
+        let response = har_response(entry.response);
+
+        if let Some((_, _, responses)) = routes.iter_mut().find(|(other, _, _)| *other == key) {
+            responses.push(response);
+            continue;
+        }
+
+        let headers: HashMap<String, String> = entry
+            .request
+            .headers
+            .into_iter()
+            .filter(|header| {
+                !is_client_header(&header.name)
+                    && !match_options
+                        .ignored_headers
+                        .iter()
+                        .any(|ignored| ignored.eq_ignore_ascii_case(&header.name))
+            })
+            .map(|header| (header.name.to_lowercase(), header.value))
+            .collect();
+
+        let route = RouteConfiguration {
+            name: Some(format!("{} /{}", entry.request.method, url)),
+            group: (!host.is_empty()).then_some(host),
+            method: Some(Value::String(entry.request.method)),
+            url,
+            headers: (!headers.is_empty()).then_some(headers),
+            query: (!query.is_empty()).then(|| query.into_iter().collect()),
+            body: None,
+            response: Responses::Sequence(vec![]),
+        };
+        routes.push((key, route, vec![response]));
+    }
+
+    routes
+        .into_iter()
+        .map(|(_, mut route, mut responses)| {
+            route.response = if responses.len() == 1 {
+                Responses::Single(responses.remove(0))
+            } else {
+                Responses::Sequence(responses)
+            };
+            route
+        })
+        .collect()
+}
+
+/// Converts the entries of an HTTP Archive into journal entries, to replay the recorded requests.
+///
+/// Entries without a response are skipped. Entries whose start time can't be read are given the time of the
+/// previous entry.
+///
+/// # Arguments
+///
+/// * `har` - The deserialized HTTP Archive.
+///
+/// # Returns
+///
+/// Returns the journal entries in the order they were recorded.
+pub fn har_to_journal(har: Har) -> Vec<JournalEntry> {
+    let mut time = SystemTime::UNIX_EPOCH;
+    let mut entries = vec![];
+
+    for entry in har.log.entries {
+        if entry.response.status == 0 {
+            continue;
+        }
+        if let Some(started) = entry.started_date_time.as_deref().and_then(parse_time) {
+            time = started;
+        }
+
+        let url = entry.request.url.split('#').next().unwrap_or_default();
+        let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let url = url.find('/').map_or("/", |start| &url[start..]);
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+
+        let headers = |headers: Vec<NameValue>| -> BTreeMap<String, String> {
+            headers
+                .into_iter()
+                .filter(|header| !header.name.starts_with(':'))
+                .map(|header| (header.name.to_lowercase(), header.value))
+                .collect()
+        };
+        entries.push(JournalEntry {
+            id: entries.len() as u64 + 1,
+            time,
+            latency_ms: 0.0,
+            method: entry.request.method,
+            path: path.to_string(),
+            query: query.to_string(),
+            headers: headers(entry.request.headers),
+            body: entry
+                .request
+                .post_data
+                .and_then(|post_data| post_data.text)
+                .unwrap_or_default(),
+            route: None,
+            response: JournalResponse {
+                status: entry.response.status as u16,
+                headers: headers(entry.response.headers),
+                body: content_text(entry.response.content.text, entry.response.content.encoding),
+            },
+        });
+    }
+    entries
+}
+
+/// Parses a HAR start time, an RFC 3339 time that can hold a UTC offset.
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let offset_start = time.len().checked_sub(6)?;
+    let (time, offset) = match time.get(offset_start..) {
+        Some(offset) if offset.starts_with(['+', '-']) && offset.as_bytes()[3] == b':' => {
+            (&time[..offset_start], offset)
+        }
+        _ => (time, "+00:00"),
+    };
+    let utc = humantime::parse_rfc3339_weak(time).ok()?;
+    let hours: u64 = offset[1..3].parse().ok()?;
+    let minutes: u64 = offset[4..].parse().ok()?;
+    let shift = Duration::from_secs(hours * 3600 + minutes * 60);
+    match offset.starts_with('+') {
+        true => utc.checked_sub(shift),
+        false => utc.checked_add(shift),
+    }
+}
+
+/// Returns `true` if a header is set by the client or the transport rather than by the API call.
+fn is_client_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.starts_with(':') || name.starts_with("sec-") || CLIENT_HEADERS.contains(&name.as_str())
+}
+
+/// Splits a recorded URL into its host and its moker route, dropping the scheme and the query string.
+fn split_url(url: &str) -> (String, String) {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    match url.split_once('/') {
+        Some((host, path)) => (host.to_string(), path.trim_matches('/').to_string()),
+        None => (url.to_string(), String::new()),
+    }
+}
+
+/// Builds the moker response of a recorded response.
+fn har_response(response: HarResponse) -> Response {
+    let headers: HashMap<String, String> = response
+        .headers
+        .into_iter()
+        .filter(|header| {
+            let name = header.name.to_lowercase();
+            !name.starts_with(':') && !SERVER_MANAGED_HEADERS.contains(&name.as_str())
+        })
+        .map(|header| (header.name, header.value))
+        .collect();
+
+    let content = response.content;
+    let text = content_text(content.text, content.encoding);
+
+    let is_json = content
+        .mime_type
+        .is_some_and(|mime_type| mime_type.contains("json"));
+    let body = match serde_json::from_str(&text) {
+        Ok(body) if is_json => body,
+        _ => Value::String(text),
+    };
+
+    Response {
+        headers: (!headers.is_empty()).then_some(headers),
+        body,
+        status_code: Some(response.status),
+        delay_ms: None,
+        raw_body: true,
+    }
+}
+
+/// Returns the text of a recorded response body, decoding base64 content.
+fn content_text(text: Option<String>, encoding: Option<String>) -> String {
+    let text = text.unwrap_or_default();
+    if encoding.as_deref() != Some("base64") {
+        return text;
+    }
+    match STANDARD.decode(&text).map(String::from_utf8) {
+        Ok(Ok(text)) => text,
+        _ => {
+            warn!("HAR response body is binary and can't be replayed, using an empty body");
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    fn entry(url: &str, status: i32, text: &str) -> Value {
+        json!({
+            "request": {
+                "method": "GET",
+                "url": url,
+                "headers": [
+                    { "name": ":authority", "value": "api.example.com" },
+                    { "name": "User-Agent", "value": "Mozilla/5.0" },
+                    { "name": "Authorization", "value": "Bearer 123" }
+                ],
+                "queryString": url
+                    .split_once('?')
+                    .map(|(_, query)| query.split('&').map(|param| {
+                        let (name, value) = param.split_once('=').unwrap();
+                        json!({ "name": name, "value": value })
+                    }).collect::<Vec<Value>>())
+                    .unwrap_or_default()
+            },
+            "response": {
+                "status": status,
+                "headers": [
+                    { "name": "Content-Type", "value": "application/json" },
+                    { "name": "Content-Length", "value": "12" }
+                ],
+                "content": { "mimeType": "application/json", "text": text }
+            }
+        })
+    }
+
+    #[test]
+    fn test_har_to_routes() {
+        let har: Har = serde_json::from_value(json!({
+            "log": {
+                "entries": [
+                    entry("https://api.example.com/v1/orders/1?expand=items", 202, "{\"state\": \"pending\"}"),
+                    entry("https://api.example.com/v1/orders/2", 200, "{\"state\": \"new\"}"),
+                    entry("https://api.example.com/v1/orders/1?expand=items", 200, "{\"state\": \"done\"}"),
+                ]
+            }
+        }))
+        .unwrap();
+
+        let routes = har_to_routes(har, &MatchOptions::default());
+
+        assert_eq!(routes.len(), 2);
+        let route = &routes[0];
+        assert_eq!(route.url, "v1/orders/1");
+        assert_eq!(route.group.as_deref(), Some("api.example.com"));
+        assert_eq!(route.query.as_ref().unwrap()["expand"], "items");
+        assert_eq!(
+            route.headers.as_ref().unwrap().keys().collect::<Vec<_>>(),
+            vec!["authorization"]
+        );
+        assert_eq!(route.response.get(0).unwrap().status_code, Some(202));
+        assert_eq!(
+            route.response.get(1).unwrap().body,
+            json!({ "state": "done" })
+        );
+        assert_eq!(route.response.get(5).unwrap().status_code, Some(200));
+        assert!(route.response.get(0).unwrap().raw_body);
+        assert_eq!(
+            route
+                .response
+                .get(0)
+                .unwrap()
+                .headers
+                .as_ref()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(routes[1].query.is_none());
+    }
+
+    #[test]
+    fn test_har_to_routes_ignored() {
+        let har: Har = serde_json::from_value(json!({
+            "log": {
+                "entries": [
+                    entry("https://api.example.com/v1/orders?page=1&_=1714557600", 200, "[1]"),
+                    entry("https://api.example.com/v1/orders?page=1&_=1714557601", 200, "[2]"),
+                ]
+            }
+        }))
+        .unwrap();
+        let match_options = MatchOptions {
+            ignored_query: vec![String::from("_")],
+            ignored_headers: vec![String::from("Authorization")],
+        };
+
+        let routes = har_to_routes(har, &match_options);
+
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+        assert_eq!(route.query.as_ref().unwrap().len(), 1);
+        assert_eq!(route.query.as_ref().unwrap()["page"], "1");
+        assert!(route.headers.is_none());
+        assert_eq!(route.response.get(1).unwrap().body, json!([2]));
+    }
+
+    #[test]
+    fn test_har_to_journal() {
+        let mut first = entry("https://api.example.com/v1/orders?page=2", 200, "[]");
+        first["startedDateTime"] = json!("2024-05-01T12:00:00.000+02:00");
+        first["request"]["postData"] = json!({ "mimeType": "application/json", "text": "{}" });
+        let mut second = entry("https://api.example.com/v1/orders", 404, "");
+        second["startedDateTime"] = json!("2024-05-01T10:00:01.500Z");
+        let har: Har =
+            serde_json::from_value(json!({ "log": { "entries": [first, second] } })).unwrap();
+
+        let entries = har_to_journal(har);
+
+        assert_eq!(entries[0].path, "/v1/orders");
+        assert_eq!(entries[0].query, "page=2");
+        assert_eq!(entries[0].body, "{}");
+        assert!(!entries[0].headers.contains_key(":authority"));
+        assert_eq!(
+            entries[1].time.duration_since(entries[0].time).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(entries[1].response.status, 404);
+    }
+}
@@ -0,0 +1,464 @@
+use crate::app_state::RequestHandlingConfig;
+use crate::request_log::RequestLog;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::http::header::HeaderMap;
+use actix_web::{HttpRequest, HttpResponse};
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+/// A bounded, in-memory record of the requests received by the mock server.
+///
+/// Once full, the oldest entries are dropped to make room for the new ones.
+/// Every entry is also appended to the request log, if there is one.
+pub struct Journal {
+    /// The number of entries kept. `0` disables the journal.
+    capacity: usize,
+    /// The file the entries are appended to.
+    log: Option<RequestLog>,
+    /// The recorded requests, oldest first.
+    entries: Mutex<VecDeque<JournalEntry>>,
+    /// The identifier of the next entry.
+    next_id: AtomicU64,
+}
+
+/// A request received by the mock server, with the response it was sent.
+///
+/// This is also the record format of the request log, read back by `moker replay`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The identifier of the entry, increasing with every request.
+    pub id: u64,
+    /// When the request was received.
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
+    pub time: SystemTime,
+    /// How long it took to answer the request, in milliseconds, including the configured delays.
+    pub latency_ms: f64,
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The path of the request, without its query string.
+    pub path: String,
+    /// The query string of the request.
+    pub query: String,
+    /// The headers of the request. Repeated headers are joined with commas.
+    pub headers: BTreeMap<String, String>,
+    /// The body of the request, decoded as UTF-8.
+    pub body: String,
+    /// The route that answered the request, if any.
+    pub route: Option<MatchedRoute>,
+    /// The response sent back.
+    pub response: JournalResponse,
+}
+
+/// The route that answered a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchedRoute {
+    /// The URL of the route, with its placeholders.
+    pub url: String,
+    /// The file the route comes from, or `runtime` for routes added through the admin API.
+    pub source: String,
+    /// The position of the route within its file, or the identifier of a runtime route.
+    pub index: usize,
+}
+
+impl MatchedRoute {
+    /// Describes the route of a configuration that answered a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the route.
+    /// * `config` - The configuration of the route.
+    pub fn new(url: &str, config: &RequestHandlingConfig) -> Self {
+        Self {
+            url: url.to_string(),
+            source: config
+                .response_file_type
+                .file_name()
+                .unwrap_or("runtime")
+                .to_string(),
+            index: config.index,
+        }
+    }
+}
+
+/// The response sent back to a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalResponse {
+    /// The status code of the response.
+    pub status: u16,
+    /// The headers of the response. Repeated headers are joined with commas.
+    pub headers: BTreeMap<String, String>,
+    /// The body of the response, decoded as UTF-8. Streamed bodies aren't recorded.
+    pub body: String,
+}
+
+/// Selects journal entries. Every criterion that is given must match.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct JournalFilter {
+    /// The URL of the route that answered the request, as written in the route file.
+    pub route: Option<String>,
+    /// The HTTP method of the request, in any case.
+    pub method: Option<String>,
+    /// A regular expression the path of the request must match.
+    pub path: Option<String>,
+    /// A regular expression the body of the request must match.
+    pub body: Option<String>,
+    /// The earliest time of the request, in RFC 3339 format.
+    pub since: Option<String>,
+    /// The latest time of the request, in RFC 3339 format.
+    pub until: Option<String>,
+    /// Only the requests recorded after the one with this identifier, to poll for new requests.
+    pub after: Option<u64>,
+}
+
+/// An expectation on the number of requests matching a filter.
+///
+/// Without a count, at least one request is expected.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Verification {
+    /// The requests to count.
+    #[serde(flatten)]
+    pub filter: JournalFilter,
+    /// The exact number of matching requests.
+    pub count: Option<usize>,
+    /// The minimum number of matching requests.
+    pub at_least: Option<usize>,
+    /// The maximum number of matching requests.
+    pub at_most: Option<usize>,
+}
+
+/// The outcome of a verification.
+#[derive(Debug, Serialize)]
+pub struct VerificationResult {
+    /// Whether the number of matching requests met the expectation.
+    pub verified: bool,
+    /// The number of matching requests.
+    pub count: usize,
+}
+
+/// A `JournalFilter` with its regular expressions compiled and its times parsed.
+struct EntryMatcher<'a> {
+    route: Option<&'a str>,
+    method: Option<&'a str>,
+    path: Option<Regex>,
+    body: Option<Regex>,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    after: Option<u64>,
+}
+
+impl JournalFilter {
+    fn matcher(&self) -> Result<EntryMatcher<'_>, String> {
+        let regex = |pattern: &Option<String>, name: &str| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .map_err(|err| format!("invalid {} pattern: {}", name, err))
+        };
+        let time = |time: &Option<String>, name: &str| {
+            time.as_deref()
+                .map(humantime::parse_rfc3339_weak)
+                .transpose()
+                .map_err(|err| format!("invalid {} time: {}", name, err))
+        };
+        Ok(EntryMatcher {
+            route: self.route.as_deref().map(|route| route.trim_matches('/')),
+            method: self.method.as_deref(),
+            path: regex(&self.path, "path")?,
+            body: regex(&self.body, "body")?,
+            since: time(&self.since, "since")?,
+            until: time(&self.until, "until")?,
+            after: self.after,
+        })
+    }
+}
+
+impl EntryMatcher<'_> {
+    fn matches(&self, entry: &JournalEntry) -> bool {
+        self.route.is_none_or(|route| {
+            entry
+                .route
+                .as_ref()
+                .is_some_and(|matched| matched.url == route)
+        }) && self
+            .method
+            .is_none_or(|method| method.eq_ignore_ascii_case(&entry.method))
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|path| path.is_match(&entry.path))
+            && self
+                .body
+                .as_ref()
+                .is_none_or(|body| body.is_match(&entry.body))
+            && self.since.is_none_or(|since| entry.time >= since)
+            && self.until.is_none_or(|until| entry.time <= until)
+            && self.after.is_none_or(|after| entry.id > after)
+    }
+}
+
+impl Verification {
+    /// Returns `true` if the number of matching requests meets the expectation.
+    fn expects(&self, count: usize) -> bool {
+        let at_least = self.count.or(self.at_least).unwrap_or(match self.at_most {
+            Some(_) => 0,
+            None => 1,
+        });
+        let at_most = self.count.or(self.at_most).unwrap_or(usize::MAX);
+        (at_least..=at_most).contains(&count)
+    }
+}
+
+impl Journal {
+    /// Creates a journal keeping the last `capacity` requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The number of entries kept in memory. `0` only writes them to the request log.
+    /// * `log` - The request log every entry is appended to.
+    pub fn new(capacity: usize, log: Option<RequestLog>) -> Self {
+        Self {
+            capacity,
+            log,
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Returns `true` if requests are recorded, in memory or in the request log.
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0 || self.log.is_some()
+    }
+
+    /// Records a request and the response sent back to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `req` - The incoming request.
+    /// * `body` - The body of the incoming request.
+    /// * `route` - The route that answered the request, if any.
+    /// * `response` - The response to send back.
+    /// * `started` - When the request started being handled.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response, unchanged.
+    pub fn record(
+        &self,
+        req: &HttpRequest,
+        body: &[u8],
+        route: Option<MatchedRoute>,
+        response: HttpResponse,
+        started: Instant,
+    ) -> HttpResponse {
+        if !self.is_enabled() {
+            return response;
+        }
+
+        let (response, response_body) = response.into_parts();
+        let (response_body, recorded_body) = match response_body.try_into_bytes() {
+            Ok(bytes) => {
+                let recorded_body = String::from_utf8_lossy(&bytes).into_owned();
+                (BoxBody::new(bytes), recorded_body)
+            }
+            Err(response_body) => (response_body, String::new()),
+        };
+
+        let entry = JournalEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            time: SystemTime::now() - started.elapsed(),
+            latency_ms: started.elapsed().as_micros() as f64 / 1000.0,
+            method: req.method().to_string(),
+            path: req.path().to_string(),
+            query: req.query_string().to_string(),
+            headers: header_map(req.headers()),
+            body: String::from_utf8_lossy(body).into_owned(),
+            route,
+            response: JournalResponse {
+                status: response.status().as_u16(),
+                headers: header_map(response.headers()),
+                body: recorded_body,
+            },
+        };
+
+        if let Some(log) = &self.log {
+            log.write(&entry);
+        }
+        if self.capacity > 0 {
+            let mut entries = self.entries.lock().unwrap();
+            if entries.len() == self.capacity {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+
+        response.set_body(response_body)
+    }
+
+    /// Returns the recorded requests matching a filter, oldest first.
+    ///
+    /// # Returns
+    ///
+    /// Returns the entries, or a message if the filter holds an invalid pattern or time.
+    pub fn entries(&self, filter: &JournalFilter) -> Result<Vec<JournalEntry>, String> {
+        let matcher = filter.matcher()?;
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .iter()
+            .filter(|entry| matcher.matches(entry))
+            .cloned()
+            .collect())
+    }
+
+    /// Counts the recorded requests matching a verification and checks the expectation.
+    ///
+    /// # Returns
+    ///
+    /// Returns the result, or a message if the verification holds an invalid pattern or time.
+    pub fn verify(&self, verification: &Verification) -> Result<VerificationResult, String> {
+        let matcher = verification.filter.matcher()?;
+        let count = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| matcher.matches(entry))
+            .count();
+        Ok(VerificationResult {
+            verified: verification.expects(count),
+            count,
+        })
+    }
+
+    /// Removes every recorded request. The request log is kept.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Writes the pending records of the request log to the disk.
+    pub fn flush(&self) {
+        if let Some(log) = &self.log {
+            log.flush();
+        }
+    }
+}
+
+/// Collects headers into a sorted map, joining repeated headers with commas.
+fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut map: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        map.entry(name.to_string())
+            .and_modify(|values| {
+                values.push_str(", ");
+                values.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+    map
+}
+
+fn serialize_time<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&humantime::format_rfc3339_millis(*time))
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    humantime::parse_rfc3339_weak(&time).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn record(journal: &Journal, method: &str, path: &str, body: &str, route: Option<&str>) {
+        let req = TestRequest::default()
+            .method(method.parse().unwrap())
+            .uri(path)
+            .to_http_request();
+        let route = route.map(|url| MatchedRoute {
+            url: url.to_string(),
+            source: String::from("payments.json"),
+            index: 0,
+        });
+        journal.record(
+            &req,
+            body.as_bytes(),
+            route,
+            HttpResponse::Ok().body("done"),
+            Instant::now(),
+        );
+    }
+
+    #[test]
+    fn test_journal() {
+        let journal = Journal::new(3, None);
+        record(
+            &journal,
+            "POST",
+            "/payments",
+            r#"{"amount":10}"#,
+            Some("payments"),
+        );
+        record(
+            &journal,
+            "POST",
+            "/payments",
+            r#"{"amount":20}"#,
+            Some("payments"),
+        );
+        record(&journal, "GET", "/missing", "", None);
+
+        let payments = JournalFilter {
+            route: Some(String::from("/payments")),
+            method: Some(String::from("post")),
+            ..Default::default()
+        };
+        let entries = journal.entries(&payments).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].response.status, 200);
+        assert_eq!(entries[0].response.body, "done");
+
+        let verification = Verification {
+            filter: JournalFilter {
+                route: Some(String::from("payments")),
+                body: Some(String::from(r#""amount":\d+"#)),
+                ..Default::default()
+            },
+            count: Some(2),
+            ..Default::default()
+        };
+        assert!(journal.verify(&verification).unwrap().verified);
+        let verification = Verification {
+            filter: JournalFilter {
+                body: Some(String::from(r#""amount":20"#)),
+                ..Default::default()
+            },
+            at_most: Some(0),
+            ..Default::default()
+        };
+        assert!(!journal.verify(&verification).unwrap().verified);
+
+        // The oldest entry is dropped once the journal is full
+        record(&journal, "GET", "/missing", "", None);
+        assert_eq!(journal.entries(&payments).unwrap().len(), 1);
+        assert_eq!(journal.entries(&JournalFilter::default()).unwrap()[0].id, 2);
+
+        let invalid = JournalFilter {
+            path: Some(String::from("(")),
+            ..Default::default()
+        };
+        assert!(journal.entries(&invalid).is_err());
+    }
+}
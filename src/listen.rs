@@ -0,0 +1,194 @@
+use socket2::{Domain, Protocol, Socket, Type};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The number of pending connections a listener queues before refusing new ones.
+const BACKLOG: i32 = 1024;
+
+/// An address the server listens on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddress {
+    /// A host name or IP address, with a port.
+    Tcp { host: String, port: u16 },
+    /// The path of a Unix domain socket.
+    Unix(PathBuf),
+}
+
+impl ListenAddress {
+    /// Creates a TCP address, accepting IPv6 addresses with or without brackets.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A host name, or an IPv4 or IPv6 address.
+    /// * `port` - The port to listen on.
+    pub fn tcp(host: &str, port: u16) -> Self {
+        ListenAddress::Tcp {
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+        }
+    }
+
+    /// Opens the TCP listeners of the address, one per IP address the host resolves to.
+    ///
+    /// The unspecified IPv6 address `::` accepts IPv4 connections as well, whatever the default of the system is.
+    ///
+    /// # Returns
+    ///
+    /// Returns the listeners, or an error if the host can't be resolved or an address can't be bound.
+    pub fn tcp_listeners(&self) -> io::Result<Vec<TcpListener>> {
+        let ListenAddress::Tcp { host, port } = self else {
+            return Err(io::Error::other(format!("{} is not a TCP address", self)));
+        };
+        let addresses: Vec<SocketAddr> = (host.as_str(), *port)
+            .to_socket_addrs()
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", self, err)))?
+            .collect();
+        if addresses.is_empty() {
+            return Err(io::Error::other(format!("{} doesn't resolve", self)));
+        }
+        addresses
+            .into_iter()
+            .map(|address| {
+                tcp_listener(address).map_err(|err| {
+                    io::Error::new(err.kind(), format!("unable to bind {}: {}", address, err))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Binds a TCP listener, dual-stack if the address is the unspecified IPv6 address.
+fn tcp_listener(address: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    if address.is_ipv6() && address.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    socket.bind(&address.into())?;
+    socket.listen(BACKLOG)?;
+    Ok(socket.into())
+}
+
+/// Binds a Unix domain socket, replacing the socket left by a previous run.
+///
+/// # Arguments
+///
+/// * `path` - The path of the socket.
+///
+/// # Returns
+///
+/// Returns the listener, or an error if the path exists and isn't a socket, or can't be bound.
+#[cfg(unix)]
+pub fn unix_listener(path: &Path) -> io::Result<UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let listener = UnixListener::bind(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("unable to bind {}: {}", path.display(), err),
+        )
+    })?;
+    Ok(listener)
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    /// Parses `HOST:PORT`, `[IPV6]:PORT` or `unix:PATH`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(String::from("missing Unix socket path after 'unix:'"));
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+
+        let (host, port) = value
+            .rsplit_once(':')
+            .ok_or_else(|| format!("'{}' should be HOST:PORT or unix:PATH", value))?;
+        let is_bracketed = host.starts_with('[') && host.ends_with(']');
+        if host.is_empty() || (host.contains(':') && !is_bracketed) {
+            return Err(format!(
+                "'{}' should be HOST:PORT, with IPv6 addresses in brackets like [::1]:8080",
+                value
+            ));
+        }
+        let port = port
+            .parse()
+            .map_err(|_| format!("invalid port '{}' in '{}'", port, value))?;
+        Ok(ListenAddress::tcp(host, port))
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp { host, port } if host.contains(':') => {
+                write!(f, "[{}]:{}", host, port)
+            }
+            ListenAddress::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_listen_address_from_str() {
+        assert_eq!(
+            "0.0.0.0:8080".parse(),
+            Ok(ListenAddress::tcp("0.0.0.0", 8080))
+        );
+        let address: ListenAddress = "[::]:9000".parse().unwrap();
+        assert_eq!(address, ListenAddress::tcp("::", 9000));
+        assert_eq!(address.to_string(), "[::]:9000");
+        assert_eq!(
+            "unix:/tmp/moker.sock".parse(),
+            Ok(ListenAddress::Unix(PathBuf::from("/tmp/moker.sock")))
+        );
+
+        assert!("::1:8080".parse::<ListenAddress>().is_err());
+        assert!("localhost".parse::<ListenAddress>().is_err());
+        assert!(":8080".parse::<ListenAddress>().is_err());
+        assert!("localhost:http".parse::<ListenAddress>().is_err());
+        assert!("unix:".parse::<ListenAddress>().is_err());
+    }
+
+    #[test]
+    fn test_dual_stack_listener() {
+        let Ok(listeners) = ListenAddress::tcp("::", 0).tcp_listeners() else {
+            // IPv6 is disabled on this machine
+            return;
+        };
+        let port = listeners[0].local_addr().unwrap().port();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_ok());
+    }
+}
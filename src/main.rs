@@ -10,31 +10,75 @@
 ///
 /// # Modules
 ///
+/// - `admin` - Registers the endpoints served under the reserved `/__moker` scope.
 /// - `app_state` - Contains the definition of application state and request handling configurations.
+/// - `certificate` - Generates the certificates served with `--tls` when none is given.
 /// - `cli` - Parses command line arguments using `clap`.
+/// - `config` - Reads the `moker.toml` project configuration file.
+/// - `defaults` - Resolves the `_defaults` files inherited by the route files of a directory.
+/// - `diagnostics` - Explains which routes came close to a request that no route answered.
 /// - `file_reader` - Provides functions for reading JSON and YAML files.
 /// - `file_watcher` - Sets up the file watcher for configuration files.
+/// - `har` - Converts HTTP Archives (HAR) into route configurations.
+/// - `journal` - Records the requests received by the server, for the verification API.
+/// - `listen` - Parses and binds the TCP and Unix domain socket addresses the server listens on.
+/// - `metrics` - Counts the requests and reloads exposed in the Prometheus format.
+/// - `mockoon` - Converts Mockoon environments into route configurations.
+/// - `openapi` - Generates OpenAPI documents from the loaded routes.
+/// - `postman` - Converts Postman collections into route configurations.
+/// - `profile` - Resolves environment variable placeholders and profile overlays in route files.
+/// - `refs` - Resolves the `$ref` fragments shared between route files.
 /// - `request` - Defines structures for handling incoming requests.
+/// - `replay` - Sends recorded requests again and compares the responses with the recorded ones.
 /// - `request_handler` - Contains the default request handling logic.
+/// - `request_log` - Appends the requests and their responses to a rotated JSON Lines file.
 /// - `rex` - Defines functions for working with regular expressions.
-use crate::app_state::AppState;
+/// - `validate` - Reports the problems of route files.
+/// - `wiremock` - Converts WireMock stub mappings into route configurations.
+use crate::app_state::{AppState, MatchOptions, ResponseDefaults};
+use crate::config::{Config, CorsConfig};
 use actix_web::middleware::Logger;
-use actix_web::middleware::{Compress, NormalizePath};
+use actix_web::middleware::{Compress, Condition, NormalizePath};
 use actix_web::web::Data;
 use actix_web::{web, App, HttpServer};
 use clap::Parser;
-use cli::Cli;
-use file_watcher::file_watcher;
-use log::info;
+use cli::{Cli, Command, ExportFormat, ImportFormat};
+use file_reader::{ScanOptions, SearchPath};
+use file_watcher::{file_watcher, WatchOptions};
+use futures::channel::oneshot;
+use listen::ListenAddress;
+use log::{info, warn};
+use request_log::{RequestLog, RequestLogOptions};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
 
+mod admin;
 mod app_state;
-mod cache;
+mod certificate;
 mod cli;
+mod config;
+mod defaults;
+mod diagnostics;
 mod file_reader;
 mod file_watcher;
+mod har;
+mod journal;
+mod listen;
+mod metrics;
+mod mockoon;
+mod openapi;
+mod postman;
+mod profile;
+mod refs;
+mod replay;
 mod request;
 mod request_handler;
+mod request_log;
 mod rex;
+mod validate;
+mod wiremock;
 
 /// Main function for the Actix Web application.
 ///
@@ -52,46 +96,444 @@ async fn main() -> Result<(), std::io::Error> {
     // Parse command line arguments using `clap`
     let cli = Cli::parse();
 
+    // Read the configuration file, command line arguments take precedence over its values
+    let config = Config::find(
+        cli.config.as_deref(),
+        cli.search_path
+            .first()
+            .map(|search_path| search_path.path.as_str()),
+    )
+    .map_err(|err| std::io::Error::other(format!("Invalid configuration file {}", err)))?;
+
     // Extract port and search path from command line arguments
-    let port = cli.port;
-    let search_path = cli.search_path;
-    let cache_size = cli.cache;
+    let port = cli.port.or(config.port).unwrap_or(8080);
+    let mut search_paths = match cli.search_path.is_empty() {
+        true => config
+            .search_paths
+            .iter()
+            .map(|search_path| search_path.parse())
+            .collect::<Result<Vec<SearchPath>, String>>()
+            .map_err(|err| std::io::Error::other(format!("Invalid configuration file {}", err)))?,
+        false => cli.search_path,
+    };
+    if search_paths.is_empty() {
+        search_paths.push(SearchPath::default());
+    }
+    if cli.cache.or(config.cache).is_some() {
+        warn!("The cache size is deprecated and ignored, routes are served from memory since they are loaded");
+    }
+    let journal_size = cli.journal_size.or(config.journal_size).unwrap_or(1000);
+    let shutdown_timeout = cli
+        .shutdown_timeout
+        .or(config.shutdown_timeout)
+        .unwrap_or(5);
+    let watch_options = WatchOptions {
+        mode: cli.watch.or(config.watch).unwrap_or_default(),
+        debounce: Duration::from_millis(cli.debounce_ms.or(config.debounce_ms).unwrap_or(2000)),
+        poll_interval: Duration::from_millis(
+            cli.poll_interval_ms
+                .or(config.poll_interval_ms)
+                .unwrap_or(1000),
+        ),
+    };
+    if let Some(profile) = cli.profile.or(config.profile) {
+        info!("Using the {:?} profile", profile);
+        profile::set_profile(profile);
+    }
+    let host = cli
+        .host
+        .or(config.bind)
+        .unwrap_or(String::from("127.0.0.1"));
+    let listen_addresses = match cli.listen.is_empty() {
+        true => config
+            .listen
+            .iter()
+            .map(|address| address.parse())
+            .collect::<Result<Vec<ListenAddress>, String>>()
+            .map_err(|err| std::io::Error::other(format!("Invalid configuration file {}", err)))?,
+        false => cli.listen,
+    };
+    let scan_options = ScanOptions {
+        include: or_config(cli.include, config.include),
+        exclude: or_config(cli.exclude, config.exclude),
+    };
+    let match_options = MatchOptions {
+        ignored_query: or_config(cli.ignore_query, config.ignore_query),
+        ignored_headers: or_config(cli.ignore_header, config.ignore_header),
+    };
+    let response_defaults = ResponseDefaults {
+        headers: config.defaults.headers,
+        delay_ms: config.defaults.delay_ms,
+        fallback: config.fallback,
+    };
+    let cors = config.cors;
+    // Any TLS flag turns HTTPS on, and overrides the matching setting of the configuration file
+    let tls_flags = cli.tls
+        || cli.tls_cert.is_some()
+        || !cli.tls_hostname.is_empty()
+        || cli.tls_ca_dir.is_some();
+    let tls = match (tls_flags, config.tls) {
+        (false, None) => None,
+        (_, tls) => {
+            let mut tls = tls.unwrap_or_default();
+            if cli.tls_cert.is_some() {
+                tls.cert = cli.tls_cert;
+                tls.key = cli.tls_key;
+            }
+            tls.hostnames = or_config(cli.tls_hostname, tls.hostnames);
+            if tls.hostnames.is_empty() {
+                tls.hostnames = default_hostnames(&host, &listen_addresses);
+            }
+            tls.ca_dir = cli.tls_ca_dir.or(tls.ca_dir);
+            Some(tls)
+        }
+    };
+
+    let server_url = server_url(&host, port, tls.is_some());
+
+    match cli.command {
+        Some(Command::Export { format }) => {
+            let route_map =
+                request_handler::create_route_map(&search_paths, &scan_options, &match_options);
+            return match format {
+                ExportFormat::Openapi { output } => export_openapi(
+                    &openapi::generate_openapi(&route_map, &server_url),
+                    output.as_deref(),
+                ),
+            };
+        }
+        Some(Command::Import { format }) => {
+            return match format {
+                ImportFormat::Har { file, output } => import_har(&file, &output, &match_options),
+            };
+        }
+        Some(Command::Replay {
+            file,
+            target,
+            timing,
+            compare_header,
+        }) => {
+            let entries = replay::read_recording(&file).map_err(|err| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+            })?;
+            let options = replay::ReplayOptions {
+                target: target.unwrap_or(server_url),
+                timing,
+                compare_headers: compare_header,
+            };
+            let report = replay::replay(&entries, &options);
+            println!(
+                "{} request(s) replayed against {}: {} identical, {} different, {} failed",
+                entries.len(),
+                options.target,
+                report.identical,
+                report.different,
+                report.failed
+            );
+            if report.different + report.failed > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Validate) => {
+            let diagnostics = validate::validate(&search_paths, &scan_options, &match_options);
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            let errors = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == validate::Severity::Error)
+                .count();
+            println!(
+                "{} error(s), {} warning(s)",
+                errors,
+                diagnostics.len() - errors
+            );
+            if errors > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let tls_config = match &tls {
+        Some(tls) => Some(tls.server_config().map_err(|err| {
+            std::io::Error::other(format!("Unable to load the TLS certificate: {}", err))
+        })?),
+        None => None,
+    };
 
     // Create application data with configuration map and port
     let app_data = Data::new(AppState::new(
-        request_handler::create_route_map(Some(search_path.clone())),
-        Some(port),
-        cache_size,
+        request_handler::load_route_files(&search_paths, &scan_options, &match_options, &[]),
+        Some(server_url),
+        match_options,
+        search_paths,
+        scan_options,
+        response_defaults,
     ));
 
+    let request_log = match cli.log_file.or(config.log.file) {
+        Some(path) => {
+            let invalid =
+                |err| std::io::Error::other(format!("Invalid configuration file {}", err));
+            let max_size = match cli.log_max_size {
+                Some(max_size) => Some(max_size),
+                None => config
+                    .log
+                    .max_size
+                    .as_deref()
+                    .map(request_log::parse_size)
+                    .transpose()
+                    .map_err(invalid)?,
+            };
+            let rotate_every = match cli.log_rotate_every {
+                Some(rotate_every) => Some(rotate_every),
+                None => config
+                    .log
+                    .rotate_every
+                    .as_deref()
+                    .map(request_log::parse_duration)
+                    .transpose()
+                    .map_err(invalid)?,
+            };
+            let mut redacted_headers = or_config(cli.redact_header, config.log.redact_headers);
+            redacted_headers.extend(request_log::DEFAULT_REDACTED_HEADERS.map(String::from));
+
+            let options = RequestLogOptions {
+                path,
+                max_size,
+                rotate_every,
+                keep: cli.log_keep.or(config.log.keep).unwrap_or(5),
+                redacted_headers,
+            };
+            Some(RequestLog::open(options).map_err(|err| {
+                std::io::Error::other(format!("Unable to open the request log: {}", err))
+            })?)
+        }
+        None => None,
+    };
+
+    // Requests are recorded by the default handler and queried through the admin API
+    let journal = Data::new(journal::Journal::new(journal_size, request_log));
+    let server_journal = journal.clone();
+
     // Log configured routes
-    info!(target: "actix", "Configured routes:\n {:#?}", app_data.config_map);
+    info!(target: "actix", "Configured routes:\n {:#?}", app_data.routes.load().map);
 
     // Clone app data for file watcher
     let app_data_clone = app_data.clone();
-    // Start the file watcher in a separate task
-    let watcher_task = file_watcher(search_path, app_data_clone);
+    // The file watcher stops once the server has stopped
+    let (stop_watcher, watcher_shutdown) = oneshot::channel();
+    let watcher_task = file_watcher(app_data_clone, watch_options, watcher_shutdown);
 
     // Start the Actix Web server
     let server = HttpServer::new(move || {
         App::new()
             .wrap(Compress::default())
+            .wrap(Condition::new(
+                cors.is_some(),
+                cors.as_ref()
+                    .map(CorsConfig::middleware)
+                    .unwrap_or_default(),
+            ))
             .wrap(Logger::default())
             .wrap(NormalizePath::trim())
             .app_data(app_data.clone())
+            .app_data(journal.clone())
+            .configure(admin::configure)
             .default_service(web::to(request_handler::default_request_handler))
     })
-    .bind(("127.0.0.1", port))?;
+    .shutdown_timeout(shutdown_timeout);
+    let listen_addresses = match listen_addresses.is_empty() {
+        true => vec![ListenAddress::tcp(&host, port)],
+        false => listen_addresses,
+    };
+    let mut server = server;
+    let mut socket_paths = vec![];
+    for address in listen_addresses {
+        match address {
+            ListenAddress::Tcp { .. } => {
+                for listener in address.tcp_listeners()? {
+                    server = match &tls_config {
+                        Some(tls_config) => {
+                            server.listen_rustls_0_23(listener, tls_config.clone())?
+                        }
+                        None => server.listen(listener)?,
+                    };
+                }
+            }
+            ListenAddress::Unix(_) if tls_config.is_some() => {
+                return Err(std::io::Error::other(
+                    "HTTPS isn't available on Unix domain sockets",
+                ));
+            }
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => {
+                server = server.listen_uds(listen::unix_listener(&path)?)?;
+                socket_paths.push(path);
+            }
+            #[cfg(not(unix))]
+            ListenAddress::Unix(_) => {
+                return Err(std::io::Error::other(
+                    "Unix domain sockets aren't available on this platform",
+                ));
+            }
+        }
+    }
 
-    // Start the file watcher in a separate task
+    // SIGINT and SIGTERM stop the server once in-flight requests complete, or after the shutdown timeout
     let server_task = async {
-        _ = server.run().await;
+        let result = server.run().await;
+        info!("Server stopped");
+        for path in &socket_paths {
+            _ = std::fs::remove_file(path);
+        }
+        server_journal.flush();
+        _ = stop_watcher.send(());
+        result
     };
 
     // Run file watcher and server concurrently
-    // TODO: Graceful shutdown of the file_watcher
-    futures::join!(watcher_task, server_task);
+    let (result, ()) = futures::join!(server_task, watcher_task);
+    result
+}
+
+/// Returns the values given on the command line, or the ones of the configuration file if there are none.
+fn or_config(cli: Vec<String>, config: Vec<String>) -> Vec<String> {
+    if cli.is_empty() {
+        config
+    } else {
+        cli
+    }
+}
+
+/// Returns the URL clients reach the server at, the target of replayed requests and the server of the OpenAPI document.
+///
+/// # Arguments
+///
+/// * `host` - The host given with `--host` or in the configuration file.
+/// * `port` - The port the server listens on.
+/// * `tls` - Whether the server answers HTTPS.
+fn server_url(host: &str, port: u16, tls: bool) -> String {
+    let scheme = if tls { "https" } else { "http" };
+    // Requests sent to an unspecified address are answered on the loopback interface
+    let host = match host.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() => String::from("127.0.0.1"),
+        Ok(IpAddr::V6(ip)) => format!("[{}]", ip),
+        _ => host.to_string(),
+    };
+    format!("{}://{}:{}", scheme, host, port)
+}
+
+/// Returns the names the generated certificate is valid for: the loopback names, and the hosts moker listens on.
+///
+/// # Arguments
+///
+/// * `host` - The host given with `--host` or in the configuration file.
+/// * `listen_addresses` - The addresses given with `--listen` or in the configuration file.
+fn default_hostnames(host: &str, listen_addresses: &[ListenAddress]) -> Vec<String> {
+    let mut hostnames = vec![
+        String::from("localhost"),
+        String::from("127.0.0.1"),
+        String::from("::1"),
+    ];
+    let listen_hosts = listen_addresses.iter().filter_map(|address| match address {
+        ListenAddress::Tcp { host, .. } => Some(host.as_str()),
+        ListenAddress::Unix(_) => None,
+    });
+    for host in std::iter::once(host).chain(listen_hosts) {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let is_unspecified = host.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified());
+        if !is_unspecified && !hostnames.iter().any(|name| name == host) {
+            hostnames.push(host.to_string());
+        }
+    }
+    hostnames
+}
+
+/// Writes an OpenAPI document to a file, or to the standard output if no file is given.
+///
+/// # Arguments
+///
+/// * `document` - The OpenAPI document to write.
+/// * `output` - An optional path of the file to write. Files ending with `.yaml` or `.yml` are written as YAML.
+///
+/// # Returns
+///
+/// Returns a `Result<(), std::io::Error>` indicating whether the document could be serialized and written.
+fn export_openapi(
+    document: &serde_json::Value,
+    output: Option<&Path>,
+) -> Result<(), std::io::Error> {
+    let is_yaml = output
+        .and_then(|path| path.extension())
+        .is_some_and(|ext| matches!(ext.to_str(), Some("yaml") | Some("yml")));
+
+    let content = if is_yaml {
+        serde_yaml::to_string(document).map_err(std::io::Error::other)?
+    } else {
+        serde_json::to_string_pretty(document)?
+    };
+
+    match output {
+        Some(path) => std::fs::write(path, content),
+        None => {
+            println!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+/// Converts the entries of an HTTP Archive (HAR) into JSON route files.
+///
+/// One file is written per route, named after its method and URL.
+///
+/// # Arguments
+///
+/// * `file` - The HAR file to import.
+/// * `output` - The directory to write the route files to. It is created if needed.
+/// * `match_options` - The query parameters and headers left out of the routes.
+///
+/// # Returns
+///
+/// Returns a `Result<(), std::io::Error>` indicating whether the HAR file could be read and every route file written.
+fn import_har(
+    file: &Path,
+    output: &Path,
+    match_options: &MatchOptions,
+) -> Result<(), std::io::Error> {
+    let routes = file_reader::read_har_file(std::fs::File::open(file)?, match_options)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?;
+
+    std::fs::create_dir_all(output)?;
+
+    let mut file_names = HashSet::new();
+    for route in routes {
+        let method = route
+            .method
+            .as_ref()
+            .and_then(|method| method.as_str())
+            .unwrap_or("any");
+        let stem: String = format!("{}_{}", method, route.url)
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        let mut file_name = format!("{}.json", stem);
+        let mut suffix = 1;
+        while !file_names.insert(file_name.clone()) {
+            suffix += 1;
+            file_name = format!("{}_{}.json", stem, suffix);
+        }
 
-    // Return OK
+        std::fs::write(
+            output.join(&file_name),
+            serde_json::to_string_pretty(&route)?,
+        )?;
+        info!("Imported route {} into {}", route.url, file_name);
+    }
     Ok(())
 }
@@ -0,0 +1,280 @@
+use crate::app_state::RouteTable;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The upper bounds, in seconds, of the buckets of the latency histograms.
+const LATENCY_BUCKETS: [f64; 14] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// The requests counted under a route, method and status code.
+type RequestKey = (String, String, u16);
+
+/// Counts the requests and reloads of the server, exposed in the Prometheus text format.
+///
+/// Requests that no route answered are counted with an empty `route` label.
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<RequestKey, u64>>,
+    latencies: Mutex<BTreeMap<String, Histogram>>,
+    unmatched: AtomicU64,
+    reloads: AtomicU64,
+    reload_failures: AtomicU64,
+}
+
+/// A cumulative latency histogram.
+#[derive(Default)]
+struct Histogram {
+    /// The number of observations less than or equal to each bucket of `LATENCY_BUCKETS`.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    /// The sum of the observations, in seconds.
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    /// Counts a request handled by the routes.
+    ///
+    /// # Arguments
+    ///
+    /// * `route` - The URL of the route that answered the request, or `None` if no route did.
+    /// * `method` - The HTTP method of the request.
+    /// * `status` - The status code of the response.
+    /// * `latency` - The time taken to answer, including the configured delay.
+    pub fn record_request(
+        &self,
+        route: Option<&str>,
+        method: &str,
+        status: u16,
+        latency: Duration,
+    ) {
+        let route = route.unwrap_or_default().to_string();
+        if route.is_empty() {
+            self.unmatched.fetch_add(1, Ordering::Relaxed);
+        }
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route.clone(), method.to_string(), status))
+            .or_default() += 1;
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry(route)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Counts a reload of the route files.
+    ///
+    /// # Arguments
+    ///
+    /// * `failed` - Whether a route file couldn't be read.
+    pub fn record_reload(&self, failed: bool) {
+        self.reloads.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.reload_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Formats the metrics in the Prometheus text exposition format.
+    ///
+    /// # Arguments
+    ///
+    /// * `routes` - The routes being served, to count them.
+    pub fn render(&self, routes: &RouteTable) -> String {
+        let mut text = String::new();
+
+        header(
+            &mut text,
+            "moker_requests_total",
+            "counter",
+            "Requests answered, by route, method and status code.",
+        );
+        for ((route, method, status), count) in self.requests.lock().unwrap().iter() {
+            _ = writeln!(
+                text,
+                "moker_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                escape(route),
+                escape(method),
+                status,
+                count
+            );
+        }
+
+        header(
+            &mut text,
+            "moker_unmatched_requests_total",
+            "counter",
+            "Requests that no route answered.",
+        );
+        _ = writeln!(
+            text,
+            "moker_unmatched_requests_total {}",
+            self.unmatched.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut text,
+            "moker_request_duration_seconds",
+            "histogram",
+            "Time taken to answer the requests, including the configured delays, by route.",
+        );
+        for (route, histogram) in self.latencies.lock().unwrap().iter() {
+            let route = escape(route);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                _ = writeln!(
+                    text,
+                    "moker_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+                    route, bound, count
+                );
+            }
+            _ = writeln!(
+                text,
+                "moker_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+                route, histogram.count
+            );
+            _ = writeln!(
+                text,
+                "moker_request_duration_seconds_sum{{route=\"{}\"}} {}",
+                route, histogram.sum
+            );
+            _ = writeln!(
+                text,
+                "moker_request_duration_seconds_count{{route=\"{}\"}} {}",
+                route, histogram.count
+            );
+        }
+
+        // The route cache is gone, routes are served from the loaded snapshot. Its metrics are
+        // still exported, always at 0, so dashboards and alerts built on them keep working.
+        let values = [
+            (
+                "moker_cache_hits_total",
+                "counter",
+                "Deprecated, always 0: routes are served from memory and no longer cached.",
+                0,
+            ),
+            (
+                "moker_cache_misses_total",
+                "counter",
+                "Deprecated, always 0: routes are served from memory and no longer cached.",
+                0,
+            ),
+            (
+                "moker_cache_entries",
+                "gauge",
+                "Deprecated, always 0: routes are served from memory and no longer cached.",
+                0,
+            ),
+            (
+                "moker_cache_capacity",
+                "gauge",
+                "Deprecated, always 0: routes are served from memory and no longer cached.",
+                0,
+            ),
+            (
+                "moker_reloads_total",
+                "counter",
+                "Reloads of the route files.",
+                self.reloads.load(Ordering::Relaxed),
+            ),
+            (
+                "moker_reload_failures_total",
+                "counter",
+                "Reloads during which a route file couldn't be read.",
+                self.reload_failures.load(Ordering::Relaxed),
+            ),
+            (
+                "moker_routes",
+                "gauge",
+                "Routes being served, runtime routes included.",
+                routes.map.values().map(Vec::len).sum::<usize>() as u64,
+            ),
+            (
+                "moker_route_file_errors",
+                "gauge",
+                "Route files that couldn't be read the last time they were loaded.",
+                routes
+                    .loaded_files
+                    .iter()
+                    .filter(|file| file.error.is_some())
+                    .count() as u64,
+            ),
+        ];
+        for (name, kind, help, value) in values {
+            header(&mut text, name, kind, help);
+            _ = writeln!(text, "{} {}", name, value);
+        }
+        text
+    }
+}
+
+/// Writes the `HELP` and `TYPE` lines of a metric.
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(text, "# HELP {} {}", name, help);
+    _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_request(Some("users/{id}"), "GET", 200, Duration::from_millis(30));
+        metrics.record_request(Some("users/{id}"), "GET", 200, Duration::from_millis(3));
+        metrics.record_request(None, "POST", 501, Duration::from_millis(1));
+        metrics.record_reload(false);
+        metrics.record_reload(true);
+
+        let text = metrics.render(&RouteTable::new(vec![]));
+
+        assert!(text.contains(
+            "moker_requests_total{route=\"users/{id}\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(
+            text.contains("moker_requests_total{route=\"\",method=\"POST\",status=\"501\"} 1\n")
+        );
+        assert!(text.contains("moker_unmatched_requests_total 1\n"));
+        assert!(text.contains(
+            "moker_request_duration_seconds_bucket{route=\"users/{id}\",le=\"0.005\"} 1\n"
+        ));
+        assert!(text.contains(
+            "moker_request_duration_seconds_bucket{route=\"users/{id}\",le=\"0.05\"} 2\n"
+        ));
+        assert!(text.contains("moker_request_duration_seconds_count{route=\"users/{id}\"} 2\n"));
+        assert!(text.contains("moker_reloads_total 2\n"));
+        assert!(text.contains("moker_reload_failures_total 1\n"));
+        assert!(text.contains("moker_routes 0\n"));
+        assert!(text.contains("moker_cache_misses_total 0\n"));
+        assert!(text.contains("moker_cache_capacity 0\n"));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
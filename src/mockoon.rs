@@ -0,0 +1,459 @@
+use crate::request::{Response, Responses, RouteConfiguration, SERVER_MANAGED_HEADERS};
+use log::warn;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Returns `true` if a JSON document is a Mockoon environment.
+///
+/// # Arguments
+///
+/// * `value` - The parsed JSON document.
+pub fn is_mockoon_environment(value: &Value) -> bool {
+    value["routes"].is_array()
+        && (value["lastMigration"].is_number() || value["endpointPrefix"].is_string())
+}
+
+/// Converts the routes of a Mockoon environment into route configurations.
+///
+/// Responses guarded by rules come first, each translated into moker guards, followed by the default response.
+/// Routes in sequential mode become a response sequence. Anything that can't be translated is reported
+/// with a warning; responses whose rules can't be translated are skipped so they don't shadow the default one.
+///
+/// # Arguments
+///
+/// * `value` - The parsed Mockoon environment.
+/// * `path` - The path of the environment file, used to resolve the files of `FILE` responses.
+///
+/// # Returns
+///
+/// Returns the route configurations in the order they should be tried.
+pub fn mockoon_to_routes(value: Value, path: &Path) -> Vec<RouteConfiguration> {
+    let prefix = value["endpointPrefix"].as_str().unwrap_or_default();
+    let environment_headers = key_values(&value["headers"]);
+
+    let mut routes = vec![];
+    for route in value["routes"].as_array().into_iter().flatten() {
+        let endpoint = route["endpoint"].as_str().unwrap_or_default();
+        let label = format!("{:?} route '{}'", path, endpoint);
+
+        if route["type"].as_str().is_some_and(|kind| kind != "http") {
+            warn!("{}: only HTTP routes are supported, skipping", label);
+            continue;
+        }
+
+        let url = translate_endpoint(prefix, endpoint, &label);
+        let method = match route["method"].as_str() {
+            None | Some("all") => None,
+            Some(method) => Some(Value::String(method.to_uppercase())),
+        };
+        let name = route["documentation"]
+            .as_str()
+            .filter(|documentation| !documentation.is_empty())
+            .map(String::from);
+
+        let route_for = |guards: Guards, response: Responses| RouteConfiguration {
+            name: name.clone(),
+            group: None,
+            method: method.clone(),
+            url: url.clone(),
+            headers: (!guards.headers.is_empty()).then_some(guards.headers),
+            query: (!guards.query.is_empty()).then_some(guards.query),
+            body: guards.body,
+            response,
+        };
+
+        let responses: Vec<&Value> = route["responses"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        let translate =
+            |response: &Value| translate_response(response, &environment_headers, path, &label);
+
+        match route["responseMode"].as_str() {
+            Some("SEQUENTIAL") => {
+                let sequence = responses
+                    .iter()
+                    .map(|response| translate(response))
+                    .collect();
+                routes.push(route_for(Guards::default(), Responses::Sequence(sequence)));
+                continue;
+            }
+            Some("RANDOM") => warn!(
+                "{}: random responses are not supported, only the default response is served",
+                label
+            ),
+            _ => {
+                for response in responses
+                    .iter()
+                    .filter(|response| !is_default(response, &responses))
+                {
+                    for guards in translate_rules(response, &label) {
+                        routes.push(route_for(guards, Responses::Single(translate(response))));
+                    }
+                }
+            }
+        }
+
+        match responses
+            .iter()
+            .find(|response| is_default(response, &responses))
+        {
+            Some(response) => routes.push(route_for(
+                Guards::default(),
+                Responses::Single(translate(response)),
+            )),
+            None => warn!("{}: route has no response, skipping", label),
+        }
+    }
+    routes
+}
+
+/// The guards translated from the rules of a response.
+#[derive(Debug, Default, Clone)]
+struct Guards {
+    headers: HashMap<String, String>,
+    query: HashMap<String, String>,
+    body: Option<Value>,
+}
+
+/// Returns `true` if a response is the default response of its route, which is the first one unless another is flagged as default.
+fn is_default(response: &Value, responses: &[&Value]) -> bool {
+    match responses
+        .iter()
+        .find(|response| response["default"].as_bool() == Some(true))
+    {
+        Some(default) => std::ptr::eq(*default, response),
+        None => responses
+            .first()
+            .is_some_and(|first| std::ptr::eq(*first, response)),
+    }
+}
+
+/// Converts a Mockoon endpoint into a moker route, turning `:param` segments into `{param}`.
+fn translate_endpoint(prefix: &str, endpoint: &str, label: &str) -> String {
+    let mut wildcards = 0;
+    prefix
+        .split('/')
+        .chain(endpoint.split('/'))
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            if let Some(param) = segment.strip_prefix(':') {
+                format!("{{{}}}", param)
+            } else if segment.contains(['*', '(', '?', '+']) {
+                wildcards += 1;
+                warn!(
+                    "{}: wildcards are not supported, '{}' matches a single path segment",
+                    label, segment
+                );
+                format!("{{wildcard{}}}", wildcards)
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Translates the rules of a response into the guards of the routes serving it.
+///
+/// Rules combined with `AND` make a single set of guards, rules combined with `OR` make one set per rule.
+/// Returns no guards if a rule can't be translated.
+fn translate_rules(response: &Value, label: &str) -> Vec<Guards> {
+    let rules: Vec<&Value> = response["rules"].as_array().into_iter().flatten().collect();
+    let response_label = format!(
+        "{} response '{}'",
+        label,
+        response["label"].as_str().unwrap_or_default()
+    );
+
+    if rules.is_empty() {
+        warn!(
+            "{}: response has no rule and isn't the default one, it can never be served, skipping",
+            response_label
+        );
+        return vec![];
+    }
+
+    let mut guards = vec![];
+    if response["rulesOperator"].as_str() == Some("OR") {
+        for rule in rules {
+            let mut rule_guards = Guards::default();
+            if !translate_rule(rule, &mut rule_guards, &response_label) {
+                return vec![];
+            }
+            guards.push(rule_guards);
+        }
+    } else {
+        let mut rule_guards = Guards::default();
+        for rule in rules {
+            if !translate_rule(rule, &mut rule_guards, &response_label) {
+                return vec![];
+            }
+        }
+        guards.push(rule_guards);
+    }
+    guards
+}
+
+/// Adds the guard matching a rule, returning `false` if moker can't express it.
+fn translate_rule(rule: &Value, guards: &mut Guards, label: &str) -> bool {
+    let target = rule["target"].as_str().unwrap_or_default();
+    let modifier = rule["modifier"].as_str().unwrap_or_default();
+    let value = match &rule["value"] {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    let operator = rule["operator"].as_str().unwrap_or("equals");
+
+    if rule["invert"].as_bool() == Some(true) {
+        warn!(
+            "{}: inverted rules are not supported, skipping the response",
+            label
+        );
+        return false;
+    }
+
+    match (target, operator) {
+        ("query", "equals") if !modifier.is_empty() => {
+            guards.query.insert(modifier.to_string(), value);
+            true
+        }
+        ("header", "equals") if !modifier.is_empty() => {
+            warn!(
+                "{}: header '{}' is only checked for presence, its value isn't compared",
+                label, modifier
+            );
+            guards.headers.insert(modifier.to_lowercase(), value);
+            true
+        }
+        ("header", "regex") if !modifier.is_empty() => {
+            warn!(
+                "{}: header '{}' is only checked for presence, the regular expression '{}' isn't applied",
+                label, modifier, value
+            );
+            guards.headers.insert(modifier.to_lowercase(), value);
+            true
+        }
+        ("body", "equals") if modifier.is_empty() => {
+            add_body_guard(guards, Value::String(value), label)
+        }
+        ("body", "equals") => {
+            // `user.id` with the value `1` becomes `{"user": {"id": 1}}`
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            let guard = modifier
+                .trim_start_matches("$.")
+                .rsplit('.')
+                .fold(value, |value, key| {
+                    Value::Object(Map::from_iter([(key.to_string(), value)]))
+                });
+            add_body_guard(guards, guard, label)
+        }
+        _ => {
+            warn!(
+                "{}: '{}' rules on '{}' are not supported, skipping the response",
+                label, operator, target
+            );
+            false
+        }
+    }
+}
+
+/// Adds a body guard to the guards of rules combined with `AND`, returning `false` if it contradicts them.
+///
+/// Rules on different properties of a JSON body are merged into a single containment object.
+fn add_body_guard(guards: &mut Guards, guard: Value, label: &str) -> bool {
+    let merged = match guards.body.take() {
+        None => Some(guard),
+        Some(body) => merge_body_guards(body, guard),
+    };
+    match merged {
+        Some(body) => {
+            guards.body = Some(body);
+            true
+        }
+        None => {
+            warn!(
+                "{}: body rules can't be combined into a single guard, skipping the response",
+                label
+            );
+            false
+        }
+    }
+}
+
+/// Merges two JSON body guards, or returns `None` if a request body can't satisfy both with a single guard.
+fn merge_body_guards(body: Value, guard: Value) -> Option<Value> {
+    match (body, guard) {
+        (Value::Object(mut body), Value::Object(guard)) => {
+            for (key, value) in guard {
+                let value = match body.remove(&key) {
+                    Some(existing) => merge_body_guards(existing, value)?,
+                    None => value,
+                };
+                body.insert(key, value);
+            }
+            Some(Value::Object(body))
+        }
+        (body, guard) => (body == guard).then_some(body),
+    }
+}
+
+/// Translates a Mockoon response, merging the environment headers with its own.
+fn translate_response(
+    response: &Value,
+    environment_headers: &HashMap<String, String>,
+    path: &Path,
+    label: &str,
+) -> Response {
+    let mut headers = environment_headers.clone();
+    headers.extend(key_values(&response["headers"]));
+    headers.retain(|key, _| !SERVER_MANAGED_HEADERS.contains(&key.to_lowercase().as_str()));
+
+    let body = match response["bodyType"].as_str() {
+        Some("FILE") => {
+            let file_path = response["filePath"].as_str().unwrap_or_default();
+            let directory = path.parent().unwrap_or(Path::new("."));
+            fs::read_to_string(directory.join(file_path)).unwrap_or_else(|err| {
+                warn!(
+                    "{}: unable to read file '{}' ({}), serving an empty body",
+                    label, file_path, err
+                );
+                String::new()
+            })
+        }
+        Some("DATABUCKET") => {
+            warn!(
+                "{}: data buckets are not supported, serving an empty body",
+                label
+            );
+            String::new()
+        }
+        _ => response["body"].as_str().unwrap_or_default().to_string(),
+    };
+
+    if body.contains("{{") && response["disableTemplating"].as_bool() != Some(true) {
+        warn!(
+            "{}: templating is not supported, the body is served as written",
+            label
+        );
+    }
+
+    Response {
+        headers: (!headers.is_empty()).then_some(headers),
+        body: Value::String(body),
+        status_code: Some(response["statusCode"].as_i64().unwrap_or(200) as i32),
+        delay_ms: response["latency"].as_u64().filter(|latency| *latency > 0),
+        raw_body: true,
+    }
+}
+
+/// Collects a Mockoon list of `{ "key": ..., "value": ... }` pairs.
+fn key_values(value: &Value) -> HashMap<String, String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|pair| {
+            Some((
+                pair["key"].as_str()?.to_string(),
+                pair["value"].as_str().unwrap_or_default().to_string(),
+            ))
+        })
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    fn response(label: &str, status: i64, rules: Value, operator: &str) -> Value {
+        json!({
+            "label": label,
+            "statusCode": status,
+            "body": label,
+            "rules": rules,
+            "rulesOperator": operator,
+        })
+    }
+
+    fn environment(route: Value) -> Value {
+        json!({ "lastMigration": 32, "endpointPrefix": "api", "routes": [route] })
+    }
+
+    fn rule(target: &str, modifier: &str, value: &str, operator: &str) -> Value {
+        json!({ "target": target, "modifier": modifier, "value": value, "operator": operator })
+    }
+
+    #[test]
+    fn test_mockoon_rules() {
+        let route = json!({
+            "type": "http",
+            "method": "post",
+            "endpoint": "users/:id",
+            "responses": [
+                response("default", 200, json!([]), "OR"),
+                response("both", 201, json!([
+                    rule("body", "user.id", "1", "equals"),
+                    rule("body", "user.name", "ann", "equals"),
+                    rule("query", "full", "true", "equals"),
+                ]), "AND"),
+                response("either", 202, json!([
+                    rule("header", "X-Token", "^a", "regex"),
+                    rule("query", "page", "2", "equals"),
+                ]), "OR"),
+                response("unsupported", 203, json!([
+                    rule("header", "X-Token", "a", "null"),
+                ]), "OR"),
+                response("contradiction", 204, json!([
+                    rule("body", "user.id", "1", "equals"),
+                    rule("body", "user.id", "2", "equals"),
+                ]), "AND"),
+            ],
+        });
+
+        let routes = mockoon_to_routes(environment(route), Path::new("mockoon.json"));
+
+        assert_eq!(routes.len(), 4);
+        assert!(routes.iter().all(|route| route.url == "api/users/{id}"));
+        assert_eq!(
+            routes[0].body,
+            Some(json!({ "user": { "id": 1, "name": "ann" } }))
+        );
+        assert_eq!(routes[0].query.as_ref().unwrap()["full"], "true");
+        assert!(routes[1].headers.as_ref().unwrap().contains_key("x-token"));
+        assert!(routes[1].query.is_none());
+        assert_eq!(routes[2].query.as_ref().unwrap()["page"], "2");
+        assert!(routes[2].headers.is_none());
+        let default = &routes[3];
+        assert!(default.headers.is_none() && default.query.is_none() && default.body.is_none());
+        assert_eq!(default.response.get(0).unwrap().body, json!("default"));
+    }
+
+    #[test]
+    fn test_mockoon_sequential() {
+        let route = json!({
+            "method": "get",
+            "endpoint": "status",
+            "responseMode": "SEQUENTIAL",
+            "responses": [
+                response("first", 503, json!([]), "OR"),
+                response("second", 200, json!([]), "OR"),
+            ],
+        });
+
+        let routes = mockoon_to_routes(environment(route), Path::new("mockoon.json"));
+
+        assert_eq!(routes.len(), 1);
+        assert!(
+            matches!(&routes[0].response, Responses::Sequence(sequence) if sequence.len() == 2)
+        );
+        assert_eq!(routes[0].response.get(0).unwrap().status_code, Some(503));
+        assert_eq!(routes[0].response.get(1).unwrap().status_code, Some(200));
+    }
+}
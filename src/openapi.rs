@@ -0,0 +1,260 @@
+use crate::app_state::RouteMap;
+use crate::request::{Response, RouteConfiguration};
+use actix_web::http::StatusCode;
+use serde_json::{json, Map, Value};
+
+/// HTTP methods that OpenAPI allows as keys of a path item.
+const OPENAPI_METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Generates an OpenAPI 3 document describing the loaded routes.
+///
+/// Every route of the route map is turned into an operation, tagged with its `group` if it has one.
+/// Path placeholders (e.g. `{id}`) become path parameters, required request headers and query values become header and
+/// query parameters, and each configured response body is used as the example from which its schema is inferred.
+///
+/// # Arguments
+///
+/// * `config_map` - The route-to-configuration mappings currently served by moker.
+/// * `server_url` - The URL clients reach the server at, used for the `servers` entry.
+///
+/// # Returns
+///
+/// Returns the OpenAPI document as a `serde_json::Value`.
+pub fn generate_openapi(config_map: &RouteMap, server_url: &str) -> Value {
+    let mut paths = Map::new();
+
+    for (route, configs) in config_map {
+        for config in configs {
+            let route_configuration = &config.route;
+
+            let path_item = paths
+                .entry(format!("/{}", route))
+                .or_insert_with(|| Value::Object(Map::new()));
+
+            let operation = operation_for_route(route, route_configuration);
+            for method in methods_for_route(route_configuration) {
+                // Configurations are tried in order, so the first one answering a method wins
+                if path_item.get(&method).is_none() {
+                    path_item[method] = operation.clone();
+                }
+            }
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "moker",
+            "description": "Generated from the routes served by moker",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": server_url }],
+        "paths": paths,
+    })
+}
+
+/// Returns the lowercase OpenAPI methods a route answers to.
+///
+/// Routes without a `method` restriction accept every method, they are exported as `get` only to keep the document readable.
+fn methods_for_route(route_configuration: &RouteConfiguration) -> Vec<String> {
+    let methods: Vec<String> = match &route_configuration.method {
+        Some(Value::String(method)) => vec![method.to_lowercase()],
+        Some(Value::Array(methods)) => methods
+            .iter()
+            .filter_map(|method| method.as_str())
+            .map(str::to_lowercase)
+            .collect(),
+        _ => vec![],
+    };
+
+    let methods: Vec<String> = methods
+        .into_iter()
+        .filter(|method| OPENAPI_METHODS.contains(&method.as_str()))
+        .collect();
+
+    if methods.is_empty() {
+        vec![String::from("get")]
+    } else {
+        methods
+    }
+}
+
+/// Builds the OpenAPI operation object for a single route.
+fn operation_for_route(route: &str, route_configuration: &RouteConfiguration) -> Value {
+    let mut parameters: Vec<Value> = route
+        .split('/')
+        .filter(|part| part.starts_with('{') && part.ends_with('}'))
+        .map(|part| {
+            json!({
+                "name": part.trim_matches(|c| c == '{' || c == '}'),
+                "in": "path",
+                "required": true,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect();
+
+    let mut headers: Vec<(&String, &String)> =
+        route_configuration.headers.iter().flatten().collect();
+    headers.sort();
+    parameters.extend(headers.into_iter().map(|(name, value)| {
+        json!({
+            "name": name,
+            "in": "header",
+            "required": true,
+            "schema": { "type": "string" },
+            "example": value,
+        })
+    }));
+
+    let mut query: Vec<(&String, &String)> = route_configuration.query.iter().flatten().collect();
+    query.sort();
+    parameters.extend(query.into_iter().map(|(name, value)| {
+        json!({
+            "name": name,
+            "in": "query",
+            "required": true,
+            "schema": { "type": "string" },
+            "example": value,
+        })
+    }));
+
+    // A sequence documents each of its status codes, the first response of a code is used as the example
+    let mut responses = Map::new();
+    for response in route_configuration.response.iter() {
+        let status_code = response.status_code.unwrap_or(200).to_string();
+        if !responses.contains_key(&status_code) {
+            responses.insert(status_code, response_object(response));
+        }
+    }
+
+    let mut operation = json!({ "responses": responses });
+    if let Some(name) = &route_configuration.name {
+        operation["summary"] = Value::String(name.clone());
+    }
+    if let Some(group) = &route_configuration.group {
+        operation["tags"] = json!([group]);
+    }
+    if !parameters.is_empty() {
+        operation["parameters"] = Value::Array(parameters);
+    }
+    operation
+}
+
+/// Builds the OpenAPI response object for a single response.
+fn response_object(response: &Response) -> Value {
+    let description = u16::try_from(response.status_code.unwrap_or(200))
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .and_then(|code| code.canonical_reason())
+        .unwrap_or("Response");
+
+    let mut content_type = String::from("application/json");
+    let mut response_headers = Map::new();
+    for (name, value) in response.headers.iter().flatten() {
+        if name.eq_ignore_ascii_case("content-type") {
+            content_type = value.clone();
+        } else {
+            response_headers.insert(
+                name.clone(),
+                json!({ "schema": { "type": "string" }, "example": value }),
+            );
+        }
+    }
+
+    let mut response_object = json!({
+        "description": description,
+        "content": {
+            content_type: {
+                "schema": infer_schema(&response.body),
+                "example": response.body,
+            }
+        },
+    });
+    if !response_headers.is_empty() {
+        response_object["headers"] = Value::Object(response_headers);
+    }
+    response_object
+}
+
+/// Infers a JSON schema from an example value.
+///
+/// Arrays take the schema of their first element, objects list the schema of every property.
+fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({ "nullable": true }),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(number) if number.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(items) => json!({
+            "type": "array",
+            "items": items.first().map(infer_schema).unwrap_or_else(|| json!({})),
+        }),
+        Value::Object(properties) => {
+            let properties: Map<String, Value> = properties
+                .iter()
+                .map(|(name, value)| (name.clone(), infer_schema(value)))
+                .collect();
+            json!({ "type": "object", "properties": properties })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_infer_schema() {
+        let body = json!({
+            "name": "Amex Card",
+            "balance": 900.22,
+            "limit": 5000,
+            "active": true,
+            "tags": ["gold"],
+        });
+
+        assert_eq!(
+            infer_schema(&body),
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "balance": { "type": "number" },
+                    "limit": { "type": "integer" },
+                    "active": { "type": "boolean" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_operation_for_route() {
+        let route_configuration: RouteConfiguration = serde_json::from_value(json!({
+            "name": "account_details",
+            "url": "account/v1/user/{id}",
+            "method": ["GET", "post"],
+            "headers": { "api_token": "123" },
+            "response": { "status_code": 201, "body": { "id": "1" } }
+        }))
+        .unwrap();
+
+        let operation = operation_for_route("account/v1/user/{id}", &route_configuration);
+
+        assert_eq!(methods_for_route(&route_configuration), vec!["get", "post"]);
+        assert_eq!(operation["summary"], "account_details");
+        assert_eq!(operation["parameters"][0]["name"], "id");
+        assert_eq!(operation["parameters"][0]["in"], "path");
+        assert_eq!(operation["parameters"][1]["name"], "api_token");
+        assert_eq!(operation["parameters"][1]["in"], "header");
+        assert_eq!(
+            operation["responses"]["201"]["content"]["application/json"]["example"],
+            json!({ "id": "1" })
+        );
+    }
+}
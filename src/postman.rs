@@ -0,0 +1,323 @@
+use crate::request::{Response, Responses, RouteConfiguration, SERVER_MANAGED_HEADERS};
+use log::warn;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A Postman v2.1 collection, limited to the parts moker needs.
+#[derive(Debug, Deserialize)]
+pub struct Collection {
+    #[serde(default)]
+    item: Vec<Item>,
+}
+
+/// A folder or a request of a Postman collection.
+#[derive(Debug, Deserialize)]
+struct Item {
+    name: Option<String>,
+    /// Present when the item is a folder.
+    item: Option<Vec<Item>>,
+    request: Option<Request>,
+    /// The saved example responses of a request.
+    #[serde(default)]
+    response: Vec<Example>,
+}
+
+/// A request, either in its full form or as a bare URL.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Request {
+    Url(String),
+    Full {
+        method: Option<String>,
+        #[serde(default)]
+        header: Headers,
+        url: Option<Url>,
+    },
+}
+
+/// A URL, either as a raw string or split into its components.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Url {
+    Raw(String),
+    Full {
+        raw: Option<String>,
+        path: Option<Vec<Value>>,
+    },
+}
+
+/// A list of headers. Postman also allows them to be written as a single raw string.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Headers {
+    List(Vec<Header>),
+    Raw(String),
+}
+
+impl Default for Headers {
+    fn default() -> Self {
+        Headers::List(vec![])
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    key: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+/// A saved example response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Example {
+    name: Option<String>,
+    original_request: Option<Request>,
+    code: Option<i32>,
+    #[serde(default)]
+    header: Headers,
+    body: Option<String>,
+}
+
+/// Returns `true` if a JSON document is a Postman v2.1 collection.
+///
+/// # Arguments
+///
+/// * `value` - The parsed JSON document.
+pub fn is_postman_collection(value: &Value) -> bool {
+    value["info"]["schema"]
+        .as_str()
+        .is_some_and(|schema| schema.contains("schema.getpostman.com") && schema.contains("v2.1"))
+}
+
+/// Converts the saved example responses of a Postman collection into route configurations.
+///
+/// Every example becomes one route. Folders are kept as the route `group`, with nested folders joined by `/`.
+/// Requests without any saved example have no response to serve and are skipped.
+///
+/// # Arguments
+///
+/// * `collection` - The deserialized Postman collection.
+///
+/// # Returns
+///
+/// Returns the route configurations in the order they appear in the collection.
+pub fn collection_to_routes(collection: Collection) -> Vec<RouteConfiguration> {
+    let mut routes = vec![];
+    collect_routes(collection.item, None, &mut routes);
+    routes
+}
+
+/// Walks the items of a folder and appends a route for every saved example.
+fn collect_routes(items: Vec<Item>, group: Option<String>, routes: &mut Vec<RouteConfiguration>) {
+    for item in items {
+        let name = item.name.unwrap_or_default();
+
+        if let Some(children) = item.item {
+            let group = match &group {
+                Some(group) => format!("{}/{}", group, name),
+                None => name,
+            };
+            collect_routes(children, Some(group), routes);
+            continue;
+        }
+
+        let Some(request) = item.request else {
+            continue;
+        };
+        if item.response.is_empty() {
+            warn!("Postman request '{}' has no saved example, skipping", name);
+            continue;
+        }
+
+        for example in item.response {
+            let (method, headers, url) = match example.original_request.as_ref() {
+                Some(original_request) => request_parts(original_request),
+                None => request_parts(&request),
+            };
+
+            routes.push(RouteConfiguration {
+                name: Some(match example.name {
+                    Some(example_name) => format!("{} ({})", name, example_name),
+                    None => name.clone(),
+                }),
+                group: group.clone(),
+                method: method.map(Value::String),
+                url,
+                headers: (!headers.is_empty()).then_some(headers),
+                query: None,
+                body: None,
+                response: Responses::Single(example_response(
+                    example.code,
+                    &example.header,
+                    example.body,
+                )),
+            });
+        }
+    }
+}
+
+/// Extracts the method, the enabled headers and the moker URL of a request.
+fn request_parts(request: &Request) -> (Option<String>, HashMap<String, String>, String) {
+    match request {
+        Request::Url(url) => (None, HashMap::new(), route_from_raw_url(url)),
+        Request::Full {
+            method,
+            header,
+            url,
+        } => {
+            let url = match url {
+                Some(Url::Raw(raw)) => route_from_raw_url(raw),
+                Some(Url::Full {
+                    path: Some(path), ..
+                }) => route_from_segments(path.iter().filter_map(|segment| match segment {
+                    Value::String(segment) => Some(segment.as_str()),
+                    segment => segment["value"].as_str(),
+                })),
+                Some(Url::Full { raw: Some(raw), .. }) => route_from_raw_url(raw),
+                _ => String::new(),
+            };
+            (method.clone(), enabled_headers(header), url)
+        }
+    }
+}
+
+/// Builds the moker response of a saved example.
+fn example_response(code: Option<i32>, headers: &Headers, body: Option<String>) -> Response {
+    let headers: HashMap<String, String> = enabled_headers(headers)
+        .into_iter()
+        .filter(|(key, _)| !SERVER_MANAGED_HEADERS.contains(&key.to_lowercase().as_str()))
+        .collect();
+
+    let body = body.unwrap_or_default();
+    let body = serde_json::from_str(&body).unwrap_or(Value::String(body));
+
+    Response {
+        headers: (!headers.is_empty()).then_some(headers),
+        body,
+        status_code: code,
+        delay_ms: None,
+        raw_body: true,
+    }
+}
+
+/// Returns the headers that are not disabled.
+fn enabled_headers(headers: &Headers) -> HashMap<String, String> {
+    match headers {
+        Headers::List(headers) => headers
+            .iter()
+            .filter(|header| !header.disabled)
+            .map(|header| (header.key.clone(), header.value.clone()))
+            .collect(),
+        Headers::Raw(raw) => raw
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect(),
+    }
+}
+
+/// Converts a raw Postman URL such as `{{baseUrl}}/users/:id?page=1` into a moker route.
+///
+/// The scheme, the host (or the leading variable standing for it) and the query string are dropped.
+fn route_from_raw_url(raw: &str) -> String {
+    let raw = raw.split(['?', '#']).next().unwrap_or_default();
+    let raw = match raw.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+        None if raw.starts_with("{{") => raw.split_once('/').map_or("", |(_, path)| path),
+        None => raw,
+    };
+    route_from_segments(raw.split('/'))
+}
+
+/// Joins URL segments into a moker route, turning `:param` and `{{param}}` segments into `{param}`.
+fn route_from_segments<'a>(segments: impl Iterator<Item = &'a str>) -> String {
+    segments
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            if let Some(param) = segment.strip_prefix(':') {
+                format!("{{{}}}", param)
+            } else if let Some(param) = segment
+                .strip_prefix("{{")
+                .and_then(|segment| segment.strip_suffix("}}"))
+            {
+                format!("{{{}}}", param)
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_route_from_raw_url() {
+        let urls = vec![
+            ("{{baseUrl}}/users/:id?page=1", "users/{id}"),
+            (
+                "https://api.example.com/v1/users/:id/cards",
+                "v1/users/{id}/cards",
+            ),
+            ("/accounts/{{accountId}}", "accounts/{accountId}"),
+            ("{{baseUrl}}", ""),
+        ];
+
+        for (raw, route) in urls {
+            assert_eq!(route_from_raw_url(raw), route);
+        }
+    }
+
+    #[test]
+    fn test_collection_to_routes() {
+        let collection: Collection = serde_json::from_value(json!({
+            "info": {
+                "name": "Accounts",
+                "schema": "https://schema.getpostman.com/json/collection/v2.1.0/collection.json"
+            },
+            "item": [{
+                "name": "Users",
+                "item": [{
+                    "name": "Get user",
+                    "request": {
+                        "method": "GET",
+                        "header": [{ "key": "api_token", "value": "123" }],
+                        "url": { "raw": "{{baseUrl}}/users/:id", "path": ["users", ":id"] }
+                    },
+                    "response": [{
+                        "name": "Found",
+                        "code": 200,
+                        "header": [
+                            { "key": "Content-Type", "value": "application/json" },
+                            { "key": "Content-Length", "value": "12" }
+                        ],
+                        "body": "{\"id\": \"1\"}"
+                    }]
+                }]
+            }]
+        }))
+        .unwrap();
+
+        let routes = collection_to_routes(collection);
+
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+        assert_eq!(route.url, "users/{id}");
+        assert_eq!(route.name.as_deref(), Some("Get user (Found)"));
+        assert_eq!(route.group.as_deref(), Some("Users"));
+        assert_eq!(route.method, Some(json!("GET")));
+        assert_eq!(route.headers.as_ref().unwrap()["api_token"], "123");
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(200));
+        assert_eq!(response.body, json!({ "id": "1" }));
+        assert_eq!(response.headers.as_ref().unwrap().len(), 1);
+    }
+}
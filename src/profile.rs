@@ -0,0 +1,249 @@
+use serde_json::Value;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The profile selected with `--profile`, choosing the overlay files patching the route files.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Selects the profile whose overlay files are applied. Only the first call has an effect.
+///
+/// # Arguments
+///
+/// * `profile` - The name of the profile, such as `staging`.
+pub fn set_profile(profile: String) {
+    _ = PROFILE.set(profile);
+}
+
+/// Returns the path of the overlay of a route file for the selected profile.
+///
+/// The overlay of `account_details.json` for the `staging` profile is `account_details.staging.json`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the route file.
+///
+/// # Returns
+///
+/// Returns the path of the overlay, or `None` if no profile is selected.
+pub fn overlay_path(path: &Path) -> Option<PathBuf> {
+    let profile = PROFILE.get()?;
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+    Some(path.with_file_name(format!("{}.{}.{}", stem, profile, extension)))
+}
+
+/// Returns the route file a file is the overlay of, for the selected profile.
+///
+/// With `--profile staging`, `users.staging.json` is the overlay of `users.json` when both exist. The files
+/// of the other profiles, such as `users.prod.json`, and `users.v2.json` are route files of their own.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+///
+/// # Returns
+///
+/// Returns the path of the base route file, or `None` if the file isn't an overlay of the selected profile.
+pub fn overlay_base(path: &Path) -> Option<PathBuf> {
+    overlay_base_for(path, PROFILE.get()?)
+}
+
+fn overlay_base_for(path: &Path, profile: &str) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+    let base = stem.strip_suffix(profile)?.strip_suffix('.')?;
+    Some(path.with_file_name(format!("{}.{}", base, extension))).filter(|base| base.is_file())
+}
+
+/// Applies an overlay to a document, following JSON Merge Patch (RFC 7386).
+///
+/// Objects are merged recursively, `null` removes a key and any other value replaces the original one.
+///
+/// # Arguments
+///
+/// * `target` - The document to patch.
+/// * `patch` - The overlay.
+pub fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// An environment variable used without a default that isn't set.
+#[derive(Debug)]
+pub struct MissingVariable(pub String);
+
+impl fmt::Display for MissingVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "environment variable {} is not set and has no default",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for MissingVariable {}
+
+/// Replaces the environment variable placeholders of every string of a document.
+///
+/// `${NAME}` is replaced with the value of the `NAME` environment variable, and `${NAME:-default}` falls back
+/// to `default` when the variable is unset or empty. `$${NAME}` is kept as a literal `${NAME}`.
+/// Placeholders always produce strings.
+///
+/// # Arguments
+///
+/// * `value` - The document to resolve.
+///
+/// # Returns
+///
+/// Returns an error naming the first variable that is unset and has no default.
+pub fn interpolate(value: &mut Value) -> Result<(), MissingVariable> {
+    match value {
+        Value::String(string) if string.contains("${") => {
+            *string = interpolate_string(string)?;
+        }
+        Value::Array(values) => {
+            for value in values {
+                interpolate(value)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                interpolate(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replaces the environment variable placeholders of a string.
+fn interpolate_string(string: &str) -> Result<String, MissingVariable> {
+    let mut result = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            // `$${` is an escaped placeholder
+            result.push_str(&rest[..start - 1]);
+            result.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+
+        let placeholder = &rest[start + 2..start + end];
+        let (name, default) = match placeholder.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (placeholder, None),
+        };
+        match (
+            env::var(name).ok().filter(|value| !value.is_empty()),
+            default,
+        ) {
+            (Some(value), _) => result.push_str(&value),
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => return Err(MissingVariable(name.to_string())),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_interpolate() {
+        env::set_var("MOKER_TEST_HOST", "staging.example.com");
+        let mut value = json!({
+            "url": "https://${MOKER_TEST_HOST}/v1",
+            "token": "${MOKER_TEST_UNSET:-dev-token}",
+            "literal": ["$${MOKER_TEST_HOST}"]
+        });
+
+        interpolate(&mut value).unwrap();
+
+        assert_eq!(value["url"], "https://staging.example.com/v1");
+        assert_eq!(value["token"], "dev-token");
+        assert_eq!(value["literal"][0], "${MOKER_TEST_HOST}");
+        assert!(interpolate(&mut json!("${MOKER_TEST_UNSET}")).is_err());
+    }
+
+    #[test]
+    fn test_overlay_base() {
+        let directory = env::temp_dir().join("moker_test_overlay_base");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("users.json"), "[]").unwrap();
+
+        assert_eq!(
+            overlay_base_for(&directory.join("users.staging.json"), "staging"),
+            Some(directory.join("users.json"))
+        );
+        assert_eq!(
+            overlay_base_for(&directory.join("users.v2.json"), "staging"),
+            None
+        );
+        assert_eq!(
+            overlay_base_for(&directory.join("users.prod.json"), "staging"),
+            None
+        );
+        assert_eq!(
+            overlay_base_for(&directory.join("orders.staging.json"), "staging"),
+            None
+        );
+        assert_eq!(
+            overlay_base_for(&directory.join("users.json"), "staging"),
+            None
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut value = json!({
+            "url": "accounts",
+            "headers": { "api_token": "dev", "x-debug": "1" },
+            "response": { "status_code": 200 }
+        });
+
+        merge_patch(
+            &mut value,
+            json!({ "headers": { "api_token": "staging", "x-debug": null }, "response": { "delay_ms": 100 } }),
+        );
+
+        assert_eq!(
+            value,
+            json!({
+                "url": "accounts",
+                "headers": { "api_token": "staging" },
+                "response": { "status_code": 200, "delay_ms": 100 }
+            })
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::file_reader::read_value;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The key of an object replaced with the fragment it references.
+const REF_KEY: &str = "$ref";
+
+/// Replaces every `{"$ref": "file#/pointer"}` object of a document with the fragment it references.
+///
+/// References are resolved relative to the file holding them, and `#/pointer` alone points into the same file.
+/// Fragments can reference other fragments. The other keys of a `$ref` object are merged into the fragment,
+/// so a shared object can be reused with a few fields changed.
+///
+/// # Arguments
+///
+/// * `value` - The document to resolve.
+/// * `path` - The path of the file holding the document.
+/// * `dependencies` - Collects the files referenced, directly or through other fragments, as canonical paths.
+///   They are collected even if resolving fails, a missing file is a dependency too.
+///
+/// # Returns
+///
+/// Returns an error if a referenced file or pointer doesn't exist, or if references form a cycle.
+pub fn resolve_refs(
+    value: &mut Value,
+    path: &Path,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<(), String> {
+    let document = value.clone();
+    let mut resolver = Resolver::default();
+    let resolved = resolver.resolve(value, path, &document);
+    dependencies.extend(resolver.dependencies);
+    resolved
+}
+
+/// Returns the canonical form of a path.
+///
+/// A file that doesn't exist, such as a removed one, is resolved through its directory, or kept as is if the directory
+/// doesn't exist either.
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| {
+        match (path.parent().map(fs::canonicalize), path.file_name()) {
+            (Some(Ok(directory)), Some(file_name)) => directory.join(file_name),
+            _ => path.to_path_buf(),
+        }
+    })
+}
+
+#[derive(Default)]
+struct Resolver {
+    /// The references being resolved, to detect cycles.
+    stack: Vec<String>,
+    /// The files referenced so far.
+    dependencies: HashSet<PathBuf>,
+}
+
+impl Resolver {
+    fn resolve(&mut self, value: &mut Value, path: &Path, document: &Value) -> Result<(), String> {
+        match value {
+            Value::Object(map) => match map.get(REF_KEY).and_then(Value::as_str) {
+                Some(reference) => {
+                    let reference = reference.to_string();
+                    map.remove(REF_KEY);
+                    let siblings = std::mem::take(map);
+                    *value = self.resolve_reference(&reference, siblings, path, document)?;
+                }
+                None => {
+                    for value in map.values_mut() {
+                        self.resolve(value, path, document)?;
+                    }
+                }
+            },
+            Value::Array(values) => {
+                for value in values {
+                    self.resolve(value, path, document)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Loads the fragment a reference points to, resolving its own references and merging the sibling keys.
+    fn resolve_reference(
+        &mut self,
+        reference: &str,
+        siblings: Map<String, Value>,
+        path: &Path,
+        document: &Value,
+    ) -> Result<Value, String> {
+        let (file, pointer) = reference.split_once('#').unwrap_or((reference, ""));
+        let (target, target_document) = if file.is_empty() {
+            (path.to_path_buf(), document.clone())
+        } else {
+            let target = path.parent().unwrap_or(Path::new(".")).join(file);
+            self.dependencies.insert(canonical(&target));
+            let target_document = read_value(&target)
+                .map_err(|err| format!("unable to read $ref '{}': {}", reference, err))?;
+            (target, target_document)
+        };
+
+        let key = format!("{}#{}", canonical(&target).display(), pointer);
+        if self.stack.contains(&key) {
+            return Err(format!(
+                "circular $ref: {} -> {}",
+                self.stack.join(" -> "),
+                key
+            ));
+        }
+
+        let mut fragment = target_document
+            .pointer(pointer)
+            .cloned()
+            .ok_or_else(|| format!("$ref '{}' points to nothing", reference))?;
+
+        self.stack.push(key);
+        let resolved = self.resolve(&mut fragment, &target, &target_document);
+        self.stack.pop();
+        resolved?;
+
+        if !siblings.is_empty() {
+            let mut siblings = Value::Object(siblings);
+            self.resolve(&mut siblings, path, document)?;
+            crate::profile::merge_patch(&mut fragment, siblings);
+        }
+        Ok(fragment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resolve_refs() {
+        let directory = std::env::temp_dir().join("moker_test_resolve_refs");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("shared.json"),
+            json!({
+                "definitions": {
+                    "admin": { "id": "1", "role": "admin", "team": { "$ref": "#/definitions/team" } },
+                    "team": { "name": "core" }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+        fs::write(
+            directory.join("cycle.json"),
+            json!({ "a": { "$ref": "#/b" }, "b": { "$ref": "#/a" } }).to_string(),
+        )
+        .unwrap();
+
+        let path = directory.join("route.json");
+        let mut value = json!({
+            "url": "users/1",
+            "response": { "body": { "$ref": "shared.json#/definitions/admin", "id": "2" } }
+        });
+        let mut dependencies = HashSet::new();
+        resolve_refs(&mut value, &path, &mut dependencies).unwrap();
+
+        assert_eq!(
+            value["response"]["body"],
+            json!({ "id": "2", "role": "admin", "team": { "name": "core" } })
+        );
+        assert_eq!(
+            dependencies,
+            HashSet::from([canonical(&directory.join("shared.json"))])
+        );
+
+        let mut value = json!({ "$ref": "cycle.json#/a" });
+        let err = resolve_refs(&mut value, &path, &mut dependencies).unwrap_err();
+        assert!(err.starts_with("circular $ref"));
+    }
+}
@@ -0,0 +1,324 @@
+use crate::har::{har_to_journal, Har};
+use crate::journal::{JournalEntry, JournalResponse};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Instant;
+
+/// Request headers that describe the recorded connection and are not sent again.
+const CONNECTION_HEADERS: [&str; 8] = [
+    "host",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+    "te",
+    "accept-encoding",
+];
+
+/// The number of characters of a body shown when it differs.
+const PREVIEW_LENGTH: usize = 80;
+
+/// How the recorded requests are paced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Timing {
+    /// Send every request as soon as the previous one is answered.
+    #[default]
+    Fast,
+    /// Wait between the requests as long as they were apart when recorded.
+    Original,
+}
+
+/// Options of a replay.
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    /// The base URL the requests are sent to, like `http://127.0.0.1:8080`.
+    pub target: String,
+    /// How the requests are paced.
+    pub timing: Timing,
+    /// The response headers compared on top of the status code and the body, in lowercase.
+    pub compare_headers: Vec<String>,
+}
+
+/// The outcome of a replay.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// The number of requests answered as they were recorded.
+    pub identical: usize,
+    /// The number of requests answered differently.
+    pub different: usize,
+    /// The number of requests that couldn't be sent.
+    pub failed: usize,
+}
+
+/// Reads the requests to replay from a request log or, for `.har` files, an HTTP Archive.
+///
+/// # Arguments
+///
+/// * `path` - The JSON Lines request log or HAR file.
+///
+/// # Returns
+///
+/// Returns the recorded requests in order, or an error if the file can't be read or holds an invalid record.
+pub fn read_recording(path: &Path) -> Result<Vec<JournalEntry>, Box<dyn std::error::Error>> {
+    let is_har = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("har"));
+    if is_har {
+        let har: Har = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        return Ok(har_to_journal(har));
+    }
+
+    let mut entries = vec![];
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|err| format!("{}:{}: {}", path.display(), index + 1, err))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Sends the recorded requests again and prints the responses that differ from the recorded ones.
+///
+/// # Arguments
+///
+/// * `entries` - The recorded requests, in the order they are sent.
+/// * `options` - Where the requests are sent, how they are paced and what is compared.
+///
+/// # Returns
+///
+/// Returns the number of identical, different and failed requests.
+pub fn replay(entries: &[JournalEntry], options: &ReplayOptions) -> ReplayReport {
+    let agent = ureq::AgentBuilder::new().redirects(0).build();
+    let mut report = ReplayReport::default();
+    let started = Instant::now();
+    let first_time = entries.first().map(|entry| entry.time);
+
+    for entry in entries {
+        if let (Timing::Original, Some(first_time)) = (options.timing, first_time) {
+            let offset = entry.time.duration_since(first_time).unwrap_or_default();
+            if let Some(wait) = offset.checked_sub(started.elapsed()) {
+                sleep(wait);
+            }
+        }
+
+        let title = format!("#{} {} {}", entry.id, entry.method, entry.path);
+        let response = match send(&agent, &options.target, entry) {
+            Ok(response) => response,
+            Err(err) => {
+                println!("{}\n    failed: {}", title, err);
+                report.failed += 1;
+                continue;
+            }
+        };
+
+        let differences = compare(&entry.response, &response, &options.compare_headers);
+        if differences.is_empty() {
+            report.identical += 1;
+        } else {
+            println!("{}", title);
+            for difference in differences {
+                println!("    {}", difference);
+            }
+            report.different += 1;
+        }
+    }
+    report
+}
+
+/// Sends a recorded request to the target.
+fn send(
+    agent: &ureq::Agent,
+    target: &str,
+    entry: &JournalEntry,
+) -> Result<JournalResponse, Box<dyn std::error::Error>> {
+    let mut url = format!("{}{}", target.trim_end_matches('/'), entry.path);
+    if !entry.query.is_empty() {
+        url = format!("{}?{}", url, entry.query);
+    }
+
+    let mut request = agent.request(&entry.method, &url);
+    for (name, value) in &entry.headers {
+        if !CONNECTION_HEADERS.contains(&name.as_str()) {
+            request = request.set(name, value);
+        }
+    }
+    let result = match entry.body.is_empty() {
+        true => request.call(),
+        false => request.send_string(&entry.body),
+    };
+    // Error statuses are responses like any other
+    let response = match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(err.into()),
+    };
+
+    let status = response.status();
+    let headers: BTreeMap<String, String> = response
+        .headers_names()
+        .into_iter()
+        .map(|name| (name.to_lowercase(), response.all(&name).join(", ")))
+        .collect();
+    Ok(JournalResponse {
+        status,
+        headers,
+        body: response.into_string()?,
+    })
+}
+
+/// Describes how a response differs from the recorded one.
+///
+/// JSON bodies are compared value by value, so formatting and key order don't matter.
+///
+/// # Arguments
+///
+/// * `expected` - The recorded response.
+/// * `actual` - The response received when replaying.
+/// * `compare_headers` - The headers compared on top of the status code and the body, in lowercase.
+///
+/// # Returns
+///
+/// Returns one line per difference, empty if the responses are the same.
+pub fn compare(
+    expected: &JournalResponse,
+    actual: &JournalResponse,
+    compare_headers: &[String],
+) -> Vec<String> {
+    let mut differences = vec![];
+    if expected.status != actual.status {
+        differences.push(format!(
+            "status: expected {}, got {}",
+            expected.status, actual.status
+        ));
+    }
+    for name in compare_headers {
+        let name = name.to_lowercase();
+        let (expected, actual) = (expected.headers.get(&name), actual.headers.get(&name));
+        if expected != actual {
+            differences.push(format!(
+                "header {}: expected {:?}, got {:?}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    match (
+        serde_json::from_str::<Value>(&expected.body),
+        serde_json::from_str::<Value>(&actual.body),
+    ) {
+        (Ok(expected), Ok(actual)) => json_differences(&expected, &actual, "", &mut differences),
+        _ if expected.body != actual.body => differences.push(format!(
+            "body: expected {:?}, got {:?}",
+            preview(&expected.body),
+            preview(&actual.body)
+        )),
+        _ => {}
+    }
+    differences
+}
+
+/// Collects the differences between two JSON values, each prefixed with its JSON pointer.
+fn json_differences(
+    expected: &Value,
+    actual: &Value,
+    pointer: &str,
+    differences: &mut Vec<String>,
+) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let pointer = format!("{}/{}", pointer, key);
+                match actual.get(key) {
+                    Some(actual) => json_differences(expected, actual, &pointer, differences),
+                    None => differences.push(format!("body {}: missing", pointer)),
+                }
+            }
+            for key in actual.keys().filter(|key| !expected.contains_key(*key)) {
+                differences.push(format!("body {}/{}: unexpected", pointer, key));
+            }
+        }
+        (Value::Array(expected_items), Value::Array(actual_items))
+            if expected_items.len() == actual_items.len() =>
+        {
+            for (index, (expected, actual)) in expected_items.iter().zip(actual_items).enumerate() {
+                json_differences(
+                    expected,
+                    actual,
+                    &format!("{}/{}", pointer, index),
+                    differences,
+                );
+            }
+        }
+        _ if expected != actual => differences.push(format!(
+            "body {}: expected {}, got {}",
+            if pointer.is_empty() { "/" } else { pointer },
+            preview(&expected.to_string()),
+            preview(&actual.to_string())
+        )),
+        _ => {}
+    }
+}
+
+/// Shortens a body to show it in a difference.
+fn preview(body: &str) -> String {
+    match body.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}…", &body[..end]),
+        None => body.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn response(status: u16, content_type: &str, body: &str) -> JournalResponse {
+        JournalResponse {
+            status,
+            headers: BTreeMap::from([(String::from("content-type"), content_type.to_string())]),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let recorded = response(
+            200,
+            "application/json",
+            r#"{"id": 1, "tags": ["a", "b"], "name": "x"}"#,
+        );
+        let same = response(200, "text/plain", r#"{"name":"x","tags":["a","b"],"id":1}"#);
+        assert!(compare(&recorded, &same, &[]).is_empty());
+
+        let changed = response(
+            201,
+            "text/plain",
+            r#"{"id": 2, "tags": ["a", "c"], "extra": true}"#,
+        );
+        assert_eq!(
+            compare(&recorded, &changed, &[String::from("Content-Type")]),
+            vec![
+                "status: expected 200, got 201",
+                r#"header content-type: expected Some("application/json"), got Some("text/plain")"#,
+                "body /id: expected 1, got 2",
+                "body /name: missing",
+                r#"body /tags/1: expected "b", got "c""#,
+                "body /extra: unexpected",
+            ]
+        );
+
+        let text = response(200, "text/plain", "hello");
+        assert_eq!(
+            compare(&text, &response(200, "text/plain", "bye"), &[]),
+            vec![r#"body: expected "hello", got "bye""#]
+        );
+    }
+}
@@ -1,20 +1,105 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Deserialize, Clone)]
+/// Response headers that are computed by the server and must not be replayed from recorded traffic.
+pub const SERVER_MANAGED_HEADERS: [&str; 5] = [
+    "content-length",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "date",
+];
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RouteConfiguration {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub method: Option<Value>,
     pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
-    pub response: Response,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+    pub response: Responses,
+}
+
+impl RouteConfiguration {
+    /// Returns the methods the route accepts, uppercased, or `None` if it accepts any method.
+    pub fn methods(&self) -> Option<HashSet<String>> {
+        match &self.method {
+            Some(Value::String(method)) => Some(HashSet::from([method.to_uppercase()])),
+            Some(Value::Array(methods)) => Some(
+                methods
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(str::to_uppercase)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if a request with some method can be answered by both routes.
+    pub fn methods_overlap(&self, other: &RouteConfiguration) -> bool {
+        match (self.methods(), other.methods()) {
+            (Some(methods), Some(other)) => !methods.is_disjoint(&other),
+            _ => true,
+        }
+    }
+}
+
+/// The response of a route, or a sequence of responses served one after the other on successive calls.
+///
+/// Once a sequence is exhausted, its last response keeps being served.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Responses {
+    Single(Response),
+    Sequence(Vec<Response>),
+}
+
+impl Responses {
+    /// Returns the response to serve for the `call`-th matching request, starting at `0`.
+    pub fn get(&self, call: usize) -> Option<&Response> {
+        match self {
+            Responses::Single(response) => Some(response),
+            Responses::Sequence(responses) => responses.get(call).or(responses.last()),
+        }
+    }
+
+    /// Returns every response, in the order they are served.
+    pub fn iter(&self) -> impl Iterator<Item = &Response> {
+        match self {
+            Responses::Single(response) => std::slice::from_ref(response).iter(),
+            Responses::Sequence(responses) => responses.iter(),
+        }
+    }
+
+    /// Returns every response mutably, in the order they are served.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Response> {
+        match self {
+            Responses::Single(response) => std::slice::from_mut(response).iter_mut(),
+            Responses::Sequence(responses) => responses.iter_mut(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Response {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub headers: Option<HashMap<String, String>>,
     pub body: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub status_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub delay_ms: Option<u64>,
+    /// Sends a string body as written instead of encoding it as a JSON string. Set for imported recordings.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw_body: bool,
 }
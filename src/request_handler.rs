@@ -1,16 +1,24 @@
-use crate::app_state::{AppState, RequestHandlingConfig, ResponseFileType};
-use crate::file_reader::{self, read_json_file, read_yaml_file};
-use crate::request::RouteConfiguration;
-use crate::rex::generate_regex_from_route;
+use crate::app_state::{
+    route_map, AppState, LoadedFile, MatchOptions, ResponseDefaults, ResponseFileType, RouteMap,
+};
+use crate::defaults::{DirectoryDefaults, DEFAULTS_FILES};
+use crate::diagnostics::NearMissReport;
+use crate::file_reader::{self, ScanOptions, SearchPath};
+use crate::journal::{Journal, MatchedRoute};
+use crate::refs;
+use crate::request::{Response, Responses, RouteConfiguration};
 use actix_web::http::Method;
 use actix_web::rt::time::sleep;
+use actix_web::web::{Bytes, Data, Query};
+use actix_web::HttpRequest;
 use actix_web::{http::StatusCode, HttpResponse, Responder};
-use actix_web::{web::Data, HttpRequest};
 use log::{info, warn};
-use std::path::PathBuf;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::Duration;
-use std::{collections::HashMap, fs::File};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Asynchronously handles incoming HTTP requests by matching routes to configuration files and generating responses.
 ///
@@ -19,146 +27,303 @@ use std::{collections::HashMap, fs::File};
 /// # Arguments
 ///
 /// * `req` - The incoming `HttpRequest` to be handled.
+/// * `body` - The body of the incoming request.
 /// * `state` - A reference to the application state (`AppState`) shared across the application.
+/// * `journal` - The journal the request and its response are recorded in.
 ///
 /// # Returns
 ///
 /// Returns an implementation of `Responder` representing the HTTP response.
-pub async fn default_request_handler(req: HttpRequest, state: Data<AppState>) -> impl Responder {
+pub async fn default_request_handler(
+    req: HttpRequest,
+    body: Bytes,
+    state: Data<AppState>,
+    journal: Data<Journal>,
+) -> impl Responder {
+    let started = Instant::now();
+    let mut matched_route = None;
+    let response = handle_request(&req, &body, &state, &mut matched_route).await;
+    state.metrics.record_request(
+        matched_route.as_ref().map(|route| route.url.as_str()),
+        req.method().as_str(),
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    journal.record(&req, &body, matched_route, response, started)
+}
+
+/// Matches a request against the routes and builds its response.
+///
+/// # Arguments
+///
+/// * `req` - The incoming `HttpRequest` to be handled.
+/// * `body` - The body of the incoming request.
+/// * `state` - A reference to the application state (`AppState`) shared across the application.
+/// * `matched_route` - Set to the route that answered the request, if any.
+///
+/// # Returns
+///
+/// Returns the `HttpResponse` to send back.
+async fn handle_request(
+    req: &HttpRequest,
+    body: &[u8],
+    state: &Data<AppState>,
+    matched_route: &mut Option<MatchedRoute>,
+) -> HttpResponse {
     let mut path = req.path();
     path = path.trim_matches('/');
 
     info!("Handling request {:?}", req);
 
-    let config_map = state.config_map.lock().unwrap().clone();
+    // The snapshot stays valid for the whole request, even if the routes are reloaded meanwhile
+    let routes = state.routes.load_full();
 
-    for route in config_map.keys() {
-        if let Ok(re) = generate_regex_from_route(route) {
-            if re.is_match(path) {
-                info!(
-                    "route:{:?} matchs the path:{:?} for regex: {:?}",
-                    route, path, re
-                );
+    // The first rejection is returned if no configuration of a matching route accepts the request
+    let mut rejection = None;
 
-                let cached_data = state.cache.lock().unwrap().get(route.to_string());
-                if let Some(route_configuration) = cached_data {
-                    info!("Cached value exists for route {}", route);
+    for (route, configs) in routes.matching(path) {
+        info!("route:{:?} matchs the path:{:?}", route, path);
+
+        for config in configs {
+            if !routes.disabled.is_empty() && routes.disabled.contains(&config.cache_key()) {
+                info!("Skipping the disabled route {}", config.cache_key());
+                continue;
+            }
+            // Routes are served from the snapshot, as they were when it was swapped in
+            let route_configuration = &config.route;
+
+            match check_request_guards(
+                route_configuration,
+                req,
+                body,
+                path,
+                route,
+                &state.match_options,
+            ) {
+                Ok(()) => {
+                    *matched_route = Some(MatchedRoute::new(route, config));
+                    let call = match route_configuration.response {
+                        Responses::Single(_) => 0,
+                        Responses::Sequence(_) => state.next_call(&config.cache_key()),
+                    };
                     return get_http_response_for_incoming_request(
                         route_configuration,
-                        &req,
+                        call,
                         path,
-                        route,
+                        &state.response_defaults,
                     )
                     .await;
                 }
-
-                let config = &config_map[route];
-                match &config.response_file_type {
-                    ResponseFileType::Json(file_name) => {
-                        return read_from_json_file(file_name, &req, path, route, state).await;
-                    }
-                    //ResponseFileType::Swagger(file_name) => {
-                    //    return read_from_swagger_file(file_name, &req, path, route, state).await;
-                    //}
-                    ResponseFileType::StaticResponse => {
-                        todo!("Static Response handling pending")
-                    }
-                    ResponseFileType::Yaml(file_name) => {
-                        return read_from_yaml_file(file_name, &req, path, route, state).await
-                    }
+                Err(message) => {
+                    rejection.get_or_insert(message);
                 }
-            } else {
-                warn!(
-                    "route:{:?} does not match the path:{:?} for regex: {:?}",
-                    route, path, re
-                );
             }
-        } else {
-            warn!("Unable to generate the regex");
         }
     }
-    HttpResponse::NotImplemented().body(format!("Unable to find route for path: '{}'", path))
+    let message = rejection.unwrap_or_else(|| format!("Unable to find route for path: '{}'", path));
+    let report = NearMissReport::new(message, &routes, state, req, body, path);
+    warn!("{}", report.to_text());
+    if let Some(fallback) = &state.response_defaults.fallback {
+        info!("Serving the fallback response: {}", report.error);
+        return build_http_response(fallback, path, &state.response_defaults).await;
+    }
+    report.into_response(req)
 }
 
-/// Reads a JSON file and converts it into an `HttpResponse`.
+/// Checks that an incoming request satisfies the method, header, query and body guards of a route.
+///
+/// Headers are only required to be present, while query parameters must have the configured value.
+/// A string body guard must appear in the request body, any other JSON body guard must be contained in the JSON request body.
 ///
 /// # Arguments
 ///
-/// * `file_name` - A reference to the name of the JSON file to be read.
+/// * `result` - An `RouteConfiguration` containing the request configuration.
 /// * `req` - An `HttpRequest` object representing the incoming request.
+/// * `body` - The body of the incoming request.
 /// * `path` - A string representing the request path.
-/// * `key` - A reference to the key associated with the configuration.
+/// * `route` - A reference to the route the request matched.
+/// * `match_options` - The headers and query parameters that are never checked.
 ///
 /// # Returns
 ///
-/// Returns an `HttpResponse` representing the response to be sent back to the client.
-async fn read_from_json_file(
-    file_name: &String,
+/// Returns `Ok(())` if the request is accepted, or a message explaining the rejection.
+fn check_request_guards(
+    result: &RouteConfiguration,
     req: &HttpRequest,
+    body: &[u8],
     path: &str,
-    route: &String,
-    state: Data<AppState>,
-) -> HttpResponse {
-    if let Ok(file) = File::open(file_name) {
-        if let Ok(result) = read_json_file(file) {
-            state
-                .cache
-                .lock()
-                .unwrap()
-                .insert(route.to_string(), result.clone());
-            get_http_response_for_incoming_request(result, req, path, route).await
-        } else {
-            HttpResponse::InternalServerError().body(format!(
-                "Unable to open file for read {}, for path: '{}'",
-                file_name, path
-            ))
+    route: &str,
+    match_options: &MatchOptions,
+) -> Result<(), String> {
+    let mismatches = guard_mismatches(result, req, body, match_options);
+    match mismatches.first() {
+        None => Ok(()),
+        Some(GuardMismatch::Method { .. }) => Err(format!(
+            "{} method is not implemented for path: '{}'",
+            req.method(),
+            path
+        )),
+        Some(_) => Err(format!(
+            "The request for URL {} doesn't satisfy the guards of the route: {}",
+            route,
+            mismatches
+                .iter()
+                .map(GuardMismatch::to_string)
+                .collect::<Vec<String>>()
+                .join("; ")
+        )),
+    }
+}
+
+/// A guard of a route that a request doesn't satisfy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuardMismatch {
+    /// The method of the request isn't one of the methods of the route.
+    Method {
+        expected: Vec<String>,
+        actual: String,
+    },
+    /// A required header is missing.
+    Header(String),
+    /// A required query parameter is missing or has another value.
+    Query {
+        name: String,
+        expected: String,
+        actual: Option<String>,
+    },
+    /// The body of the request doesn't contain the required body.
+    Body(Value),
+}
+
+impl std::fmt::Display for GuardMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GuardMismatch::Method { expected, actual } => {
+                write!(
+                    f,
+                    "method: expected {}, got {}",
+                    expected.join(" or "),
+                    actual
+                )
+            }
+            GuardMismatch::Header(name) => write!(f, "header: missing '{}'", name),
+            GuardMismatch::Query {
+                name,
+                expected,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "query: expected {}={}, got {}={}",
+                name, expected, name, actual
+            ),
+            GuardMismatch::Query {
+                name,
+                expected,
+                actual: None,
+            } => write!(f, "query: missing {}={}", name, expected),
+            GuardMismatch::Body(Value::String(expected)) => {
+                write!(f, "body: doesn't contain '{}'", expected)
+            }
+            GuardMismatch::Body(expected) => write!(f, "body: doesn't contain {}", expected),
         }
-    } else {
-        HttpResponse::InternalServerError().body(format!(
-            "Unable to read file {}, for path: '{}'",
-            file_name, path
-        ))
     }
 }
 
-/// Reads a YAML file and converts it into an `HttpResponse`.
+/// Lists the method, header, query and body guards of a route that an incoming request doesn't satisfy.
 ///
 /// # Arguments
 ///
-/// * `file_name` - A reference to the name of the YAML file to be read.
+/// * `result` - An `RouteConfiguration` containing the request configuration.
 /// * `req` - An `HttpRequest` object representing the incoming request.
-/// * `path` - A string representing the request path.
-/// * `key` - A reference to the key associated with the configuration.
+/// * `body` - The body of the incoming request.
+/// * `match_options` - The headers and query parameters that are never checked.
 ///
 /// # Returns
 ///
-/// Returns an `HttpResponse` representing the response to be sent back to the client.
-async fn read_from_yaml_file(
-    file_name: &String,
+/// Returns every unsatisfied guard, in the order they are checked. The request is accepted if there are none.
+pub fn guard_mismatches(
+    result: &RouteConfiguration,
     req: &HttpRequest,
-    path: &str,
-    route: &String,
-    state: Data<AppState>,
-) -> HttpResponse {
-    if let Ok(file) = File::open(file_name) {
-        if let Ok(result) = read_yaml_file(file) {
-            state
-                .cache
-                .lock()
-                .unwrap()
-                .insert(route.to_string(), result.clone());
-            get_http_response_for_incoming_request(result, req, path, route).await
-        } else {
-            HttpResponse::InternalServerError().body(format!(
-                "Unable to open file for read {}, for path: '{}'",
-                file_name, path
-            ))
+    body: &[u8],
+    match_options: &MatchOptions,
+) -> Vec<GuardMismatch> {
+    let mut mismatches = vec![];
+
+    if let Some(methods) = result.methods() {
+        // A single method that isn't a valid HTTP method doesn't restrict the route
+        let is_invalid = matches!(result.method, Some(Value::String(_)))
+            && methods
+                .iter()
+                .any(|method| Method::from_str(method).is_err());
+        if !is_invalid && !methods.contains(req.method().as_str()) {
+            let mut expected: Vec<String> = methods.into_iter().collect();
+            expected.sort();
+            mismatches.push(GuardMismatch::Method {
+                expected,
+                actual: req.method().to_string(),
+            });
         }
-    } else {
-        HttpResponse::InternalServerError().body(format!(
-            "Unable to read file {}, for path: '{}'",
-            file_name, path
-        ))
+    }
+
+    let required_headers = result.headers.iter().flatten().filter(|(k, _)| {
+        !match_options
+            .ignored_headers
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(k))
+    });
+    for (name, _) in required_headers {
+        if !req.headers().contains_key(name.to_lowercase().as_str()) {
+            mismatches.push(GuardMismatch::Header(name.clone()));
+        }
+    }
+
+    let incoming_query = Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(Query::into_inner)
+        .unwrap_or_default();
+    let required_query = result
+        .query
+        .iter()
+        .flatten()
+        .filter(|(k, _)| !match_options.ignored_query.contains(k));
+    for (name, expected) in required_query {
+        let actual = incoming_query.get(name);
+        if actual != Some(expected) {
+            mismatches.push(GuardMismatch::Query {
+                name: name.clone(),
+                expected: expected.clone(),
+                actual: actual.cloned(),
+            });
+        }
+    }
+
+    if let Some(required_body) = &result.body {
+        let contains_body = match required_body {
+            Value::String(required_body) => {
+                String::from_utf8_lossy(body).contains(required_body.as_str())
+            }
+            required_body => serde_json::from_slice::<Value>(body)
+                .is_ok_and(|body| json_contains(&body, required_body)),
+        };
+        if !contains_body {
+            mismatches.push(GuardMismatch::Body(required_body.clone()));
+        }
+    }
+    mismatches
+}
+
+/// Returns `true` if a JSON value contains everything an expected value describes.
+///
+/// Objects must contain every expected key with a matching value, and arrays every expected element, in any order.
+/// Any other value must be equal.
+fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(k, v)| actual.get(k).is_some_and(|actual| json_contains(actual, v))),
+        (Value::Array(actual), Value::Array(expected)) => expected
+            .iter()
+            .all(|v| actual.iter().any(|actual| json_contains(actual, v))),
+        _ => actual == expected,
     }
 }
 
@@ -167,79 +332,74 @@ async fn read_from_yaml_file(
 /// # Arguments
 ///
 /// * `result` - An `RouteConfiguration` containing the request configuration.
-/// * `req` - An `HttpRequest` object representing the incoming request.
+/// * `call` - The number of times the route was called before, used to pick the response of a sequence.
 /// * `path` - A string representing the request path.
-/// * `key` - A reference to the key associated with the configuration.
+/// * `defaults` - The headers and delay applied to every response.
 ///
 /// # Returns
 ///
 /// Returns an `HttpResponse` representing the response to be sent back to the client.
 async fn get_http_response_for_incoming_request(
-    result: RouteConfiguration,
-    req: &HttpRequest,
+    result: &RouteConfiguration,
+    call: usize,
     path: &str,
-    route: &String,
+    defaults: &ResponseDefaults,
 ) -> HttpResponse {
-    if let Some(method) = result.method {
-        if let Some(method) = method.as_str() {
-            if let Ok(method) = Method::from_str(method.to_uppercase().as_str()) {
-                if req.method() != method {
-                    return HttpResponse::NotImplemented().body(format!(
-                        "{} method is not implemented for path: '{}'",
-                        req.method(),
-                        path
-                    ));
-                }
-            }
-        } else if let Some(method) = method.as_array() {
-            let values: Vec<&str> = method.iter().filter_map(|value| value.as_str()).collect();
-
-            if !values.contains(&req.method().as_str()) {
-                return HttpResponse::NotImplemented().body(format!(
-                    "{} method is not implemented for path: '{}'",
-                    req.method(),
-                    path
-                ));
-            }
-        }
-    }
-    let incoming_headers: HashMap<String, String> = req
-        .headers()
-        .iter()
-        .map(|h| (h.0.to_string(), h.1.to_str().unwrap().to_string()))
-        .collect();
-    let required_headers = result.headers.unwrap_or_default();
-    let contains_all_headers = required_headers.iter().all(|(k, _)| {
-        incoming_headers.contains_key(k)
-        // && incoming_headers.get(k) == Some(v)
-    });
-    if !contains_all_headers {
-        return HttpResponse::NotImplemented().body(format!(
-            "The request for URL {} is missing required headers: '{:?}'. The request had {:?} headers only",
-            route, required_headers, incoming_headers
-        ));
-    }
+    let Some(response) = result.response.get(call) else {
+        return HttpResponse::NotImplemented()
+            .body(format!("No response is configured for path: '{}'", path));
+    };
+    build_http_response(response, path, defaults).await
+}
 
-    let response = result.response;
+/// Builds the `HttpResponse` of a configured response, applying the default headers and delay.
+///
+/// # Arguments
+///
+/// * `response` - The configured response.
+/// * `path` - A string representing the request path.
+/// * `defaults` - The headers and delay applied to every response.
+///
+/// # Returns
+///
+/// Returns an `HttpResponse` representing the response to be sent back to the client.
+async fn build_http_response(
+    response: &Response,
+    path: &str,
+    defaults: &ResponseDefaults,
+) -> HttpResponse {
+    // Raw string bodies are sent as written, anything else is serialized to JSON
+    let body = match &response.body {
+        Value::String(body) if response.raw_body => Ok(body.clone()),
+        body => serde_json::to_string(body),
+    };
 
-    if let Ok(body) = serde_json::to_string(&response.body) {
+    if let Ok(body) = body {
         // Start with StatusCode
-        let code = StatusCode::from_u16(response.status_code.unwrap_or(200) as u16).unwrap();
+        let status_code = response.status_code.unwrap_or(200);
+        let Some(code) = u16::try_from(status_code)
+            .ok()
+            .and_then(|code| StatusCode::from_u16(code).ok())
+        else {
+            return HttpResponse::InternalServerError().body(format!(
+                "Invalid status code {} configured for path: '{}'",
+                status_code, path
+            ));
+        };
 
         let mut http_response = HttpResponse::build(code);
 
-        // Insert Headers
-        let headers = response.headers.unwrap_or(HashMap::new());
-        for header in headers {
-            http_response.insert_header(header);
+        // Insert Headers, the ones of the route replace the default ones
+        for (name, value) in &defaults.headers {
+            http_response.insert_header((name.as_str(), value.as_str()));
+        }
+        for (name, value) in response.headers.iter().flatten() {
+            http_response.insert_header((name.as_str(), value.as_str()));
         }
 
-        if let Some(duration) = response.delay_ms {
+        if let Some(duration) = response.delay_ms.or(defaults.delay_ms) {
             sleep(Duration::from_millis(duration)).await;
         }
-        if let Some(duration) = response.delay_ms {
-            sleep(Duration::from_secs(duration)).await;
-        }
 
         // Insert Body
         http_response.body(body)
@@ -250,97 +410,416 @@ async fn get_http_response_for_incoming_request(
 
 /// Creates a map of routes to their corresponding configurations.
 ///
-/// This function sets up a directory of URLs and their respective configuration files containing response data. It reads the files, processes them, and maps each route to its configuration.
+/// This function sets up a directory of URLs and their respective configuration files containing response data. It reads the files of every search path and their subdirectories, processes them, and maps each route to its configuration.
 ///
 /// # Arguments
 ///
-/// * `search_path` - An optional string representing the path to the directory containing the configuration files. If not provided, the default is set to the current directory (`"./"`).
+/// * `search_paths` - The directories containing the route files, with their mount prefixes.
+/// * `scan_options` - The include and exclude patterns selecting the files to load.
 ///
 /// # Returns
 ///
-/// Returns a `HashMap` where the keys are route URLs and the values are associated `RequestHandlingConfig` structures.
+/// Returns a `RouteMap` where the keys are route URLs and the values are the associated `RequestHandlingConfig` structures.
 ///
 /// # Example
 ///
 /// ```rust
 /// use crate::request_handler::create_route_map;
 ///
-/// let route_map = create_route_map(Some("./config".to_string()));
+/// let route_map = create_route_map(
+///     &["./config@/api".parse().unwrap()],
+///     &ScanOptions::default(),
+///     &MatchOptions::default(),
+/// );
 /// ```
-pub fn create_route_map(search_path: Option<String>) -> HashMap<String, RequestHandlingConfig> {
-    let search_path = search_path.unwrap_or(String::from("./"));
-    let mut map = HashMap::new();
-
-    let paths = file_reader::read_directory(search_path, false);
-
-    for path in paths {
-        if let Ok(file) = File::open(path.clone()) {
-            match path.extension().unwrap().to_str() {
-                Some("json") => match read_json_file(file) {
-                    Ok(result) => {
-                        insert_json_request_into_map(result, path, &mut map);
-                    }
-                    Err(err) => warn!("Error reading JSON file: {}", err),
-                },
-                Some("yaml") | Some("yml") => match read_yaml_file(file) {
-                    Ok(result) => {
-                        insert_yaml_request_into_map(result, path, &mut map);
+pub fn create_route_map(
+    search_paths: &[SearchPath],
+    scan_options: &ScanOptions,
+    match_options: &MatchOptions,
+) -> RouteMap {
+    route_map(
+        &[],
+        &load_route_files(search_paths, scan_options, match_options, &[]),
+    )
+}
+
+/// Reads the route files of every search path.
+///
+/// Routes of a search path with a mount prefix are served under that prefix. When search paths define the same route,
+/// the one of the first search path is served and the conflict is reported.
+/// A file that can't be read anymore keeps its previous version, so a typo doesn't take its routes down.
+/// Files that can't be read are returned with their error, and without routes if they never loaded.
+///
+/// # Arguments
+///
+/// * `search_paths` - The directories containing the route files, with their mount prefixes.
+/// * `scan_options` - The include and exclude patterns selecting the files to load.
+/// * `match_options` - The query parameters and headers left out of the routes recorded in HAR files.
+/// * `previous_files` - The files loaded before, whose last good version is kept when they fail to read.
+///
+/// # Returns
+///
+/// Returns the loaded files, in the order their routes are tried.
+pub fn load_route_files(
+    search_paths: &[SearchPath],
+    scan_options: &ScanOptions,
+    match_options: &MatchOptions,
+    previous_files: &[LoadedFile],
+) -> Vec<LoadedFile> {
+    scan_route_files(
+        search_paths,
+        scan_options,
+        match_options,
+        previous_files,
+        None,
+    )
+}
+
+/// Reads the route files that changed, and the ones depending on them, keeping the other files as they were loaded.
+///
+/// The search paths are listed again, so added and removed files are noticed, but only the files that changed, or
+/// whose `$ref` fragments or profile overlay changed, are read. Every file is read again when a `_defaults` file
+/// changed, since its defaults apply to a whole directory.
+///
+/// # Arguments
+///
+/// * `search_paths` - The directories containing the route files, with their mount prefixes.
+/// * `scan_options` - The include and exclude patterns selecting the files to load.
+/// * `match_options` - The query parameters and headers left out of the routes recorded in HAR files.
+/// * `previous_files` - The files loaded before, kept when they didn't change.
+/// * `changed` - The canonical paths of the files that were created, modified or removed.
+///
+/// # Returns
+///
+/// Returns the loaded files, in the order their routes are tried.
+pub fn reload_changed_files(
+    search_paths: &[SearchPath],
+    scan_options: &ScanOptions,
+    match_options: &MatchOptions,
+    previous_files: &[LoadedFile],
+    changed: &HashSet<PathBuf>,
+) -> Vec<LoadedFile> {
+    let defaults_changed = changed.iter().any(|path| {
+        path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .is_some_and(|file_name| DEFAULTS_FILES.contains(&file_name))
+    });
+    scan_route_files(
+        search_paths,
+        scan_options,
+        match_options,
+        previous_files,
+        (!defaults_changed).then_some(changed),
+    )
+}
+
+/// A file found in a search path, before the files holding fragments are known.
+enum ScannedFile {
+    /// A file that didn't change, as it was loaded before.
+    Unchanged(LoadedFile),
+    /// A file that was read, with the files it depends on.
+    Read(
+        ResponseFileType,
+        Result<Vec<RouteConfiguration>, Box<dyn std::error::Error>>,
+        HashSet<PathBuf>,
+    ),
+    /// A file that didn't change and held fragments, only read if no file references it anymore.
+    Fragment(ResponseFileType),
+}
+
+/// Reads the route files of every search path, only reading the files that changed when `changed` is given.
+fn scan_route_files(
+    search_paths: &[SearchPath],
+    scan_options: &ScanOptions,
+    match_options: &MatchOptions,
+    previous_files: &[LoadedFile],
+    changed: Option<&HashSet<PathBuf>>,
+) -> Vec<LoadedFile> {
+    let mut loaded_files = vec![];
+    let mut origins = RouteOrigins::new();
+
+    let previous_fragments: HashSet<&PathBuf> = previous_files
+        .iter()
+        .flat_map(|file| &file.dependencies)
+        .collect();
+    let read = |response_file_type: ResponseFileType, document: Option<Value>| {
+        let mut dependencies = HashSet::new();
+        let routes = file_reader::read_routes(
+            &response_file_type,
+            document,
+            match_options,
+            &mut dependencies,
+        );
+        ScannedFile::Read(response_file_type, routes, dependencies)
+    };
+
+    // Every file is read once, the files referenced by the others are only known afterwards. A file can reference
+    // a fragment of another search path, the fragments are only known once every search path is read.
+    let mut scanned_search_paths = vec![];
+    for search_path in search_paths {
+        let mut scanned_files = vec![];
+        for path in file_reader::read_directory(&search_path.path, true, scan_options) {
+            let Some((response_file_type, document)) = file_reader::detect_file_type(&path) else {
+                warn!("Error reading file with extension: {:?}", path.extension());
+                continue;
+            };
+            let canonical_path = refs::canonical(&path);
+            let scanned_file = match changed {
+                Some(changed) if !changed.contains(&canonical_path) => {
+                    let previous = previous_files.iter().find(|file| {
+                        file.response_file_type.file_name() == response_file_type.file_name()
+                            && file.dependencies.is_disjoint(changed)
+                    });
+                    match previous {
+                        Some(previous) => ScannedFile::Unchanged(previous.clone()),
+                        None if previous_fragments.contains(&canonical_path) => {
+                            ScannedFile::Fragment(response_file_type)
+                        }
+                        None => read(response_file_type, document),
                     }
-                    Err(err) => warn!("Error reading YAML file: {}", err),
-                },
-                None | Some(&_) => {
-                    warn!("Error reading file with extension: {:?}", path.extension())
                 }
+                _ => read(response_file_type, document),
+            };
+            scanned_files.push((path, canonical_path, scanned_file));
+        }
+        scanned_search_paths.push(scanned_files);
+    }
+    let fragments: HashSet<PathBuf> = scanned_search_paths
+        .iter()
+        .flatten()
+        .flat_map(|(_, _, scanned_file)| match scanned_file {
+            ScannedFile::Unchanged(file) => file.dependencies.iter().cloned().collect(),
+            ScannedFile::Read(_, _, dependencies) => dependencies.iter().cloned().collect(),
+            ScannedFile::Fragment(_) => vec![],
+        })
+        .collect();
+
+    for (index, (search_path, scanned_files)) in
+        search_paths.iter().zip(scanned_search_paths).enumerate()
+    {
+        let mut directory_defaults =
+            DirectoryDefaults::new(&search_path.path, search_path.mount.clone());
+
+        for (path, canonical_path, scanned_file) in scanned_files {
+            if fragments.contains(&canonical_path) {
+                info!(
+                    "{:?} is referenced by other files, not loading it as a route",
+                    path
+                );
+                continue;
             }
+            let scanned_file = match scanned_file {
+                ScannedFile::Fragment(response_file_type) => read(response_file_type, None),
+                scanned_file => scanned_file,
+            };
+            let loaded_file = match scanned_file {
+                ScannedFile::Unchanged(file) => file,
+                ScannedFile::Read(response_file_type, Ok(routes), dependencies) => {
+                    let defaults = directory_defaults.for_file(&path);
+                    let routes = routes
+                        .into_iter()
+                        .map(|mut route| {
+                            defaults.apply(&mut route);
+                            Arc::new(route)
+                        })
+                        .collect();
+                    LoadedFile {
+                        response_file_type,
+                        routes,
+                        error: None,
+                        dependencies,
+                    }
+                }
+                ScannedFile::Read(response_file_type, Err(err), dependencies) => {
+                    let previous = previous_files.iter().find(|file| {
+                        file.response_file_type.file_name() == response_file_type.file_name()
+                    });
+                    match previous {
+                        Some(previous) => {
+                            warn!(
+                                "Error reading file {:?}, keeping its last good version: {}",
+                                path, err
+                            );
+                            LoadedFile {
+                                error: Some(err.to_string()),
+                                dependencies,
+                                ..previous.clone()
+                            }
+                        }
+                        None => {
+                            warn!("Error reading file {:?}: {}", path, err);
+                            LoadedFile {
+                                response_file_type,
+                                routes: vec![],
+                                error: Some(err.to_string()),
+                                dependencies,
+                            }
+                        }
+                    }
+                }
+                ScannedFile::Fragment(_) => continue,
+            };
+            report_conflicts(&loaded_file.routes, &path, index, &mut origins);
+            loaded_files.push(loaded_file);
+        }
+        for (path, err) in directory_defaults.errors {
+            warn!("Error reading defaults file {:?}: {}", path, err);
         }
     }
-    map
+    loaded_files
 }
 
-/// Inserts a JSON request configuration into the request map.
+/// The file, method and search path index of the routes loaded so far, by URL.
+type RouteOrigins = HashMap<String, Vec<(PathBuf, Option<HashSet<String>>, usize)>>;
+
+/// Warns about routes answering the same method and URL as a route of another search path.
 ///
 /// # Arguments
 ///
-/// * `result` - An `RouteConfiguration` containing the request configuration.
-/// * `path` - A `PathBuf` representing the path to the JSON file.
-/// * `map` - A mutable reference to the route map (`HashMap`).
-fn insert_json_request_into_map(
-    result: RouteConfiguration,
-    path: PathBuf,
-    map: &mut HashMap<String, RequestHandlingConfig>,
+/// * `routes` - The routes read from a file, with their defaults applied.
+/// * `path` - The path of the file.
+/// * `search_path` - The index of the search path holding the file.
+/// * `origins` - The routes loaded from the previous files.
+fn report_conflicts(
+    routes: &[Arc<RouteConfiguration>],
+    path: &Path,
+    search_path: usize,
+    origins: &mut RouteOrigins,
 ) {
-    let url = result.url.trim_matches('/');
+    for route in routes {
+        let url = route.url.trim_matches('/');
+        let loaded = origins.entry(url.to_string()).or_default();
 
-    match path.to_str() {
-        Some(path) => {
-            let config = RequestHandlingConfig::new(ResponseFileType::Json(path.to_string()));
+        let methods = route.methods();
 
-            map.insert(String::from(url), config);
+        let conflict = loaded.iter().find(|(_, other_methods, index)| {
+            *index != search_path
+                && match (&methods, other_methods) {
+                    (Some(methods), Some(other)) => !methods.is_disjoint(other),
+                    _ => true,
+                }
+        });
+        if let Some((other, _, _)) = conflict {
+            warn!(
+                "Route /{} of {:?} conflicts with {:?} from another search path, serving the first one",
+                url, path, other
+            );
         }
-        None => warn!("Error reading JSON file"),
+        loaded.push((path.to_path_buf(), methods, search_path));
     }
 }
 
-/// Inserts a YAML request configuration into the request map.
-///
-/// # Arguments
-///
-/// * `result` - An `RouteConfiguration` containing the request configuration.
-/// * `path` - A `PathBuf` representing the path to the YAML file.
-/// * `map` - A mutable reference to the route map (`HashMap`).
-fn insert_yaml_request_into_map(
-    result: RouteConfiguration,
-    path: PathBuf,
-    map: &mut HashMap<String, RequestHandlingConfig>,
-) {
-    let url = result.url.trim_matches('/');
+#[cfg(test)]
+mod tests {
 
-    match path.to_str() {
-        Some(path) => {
-            let config = RequestHandlingConfig::new(ResponseFileType::Yaml(path.to_string()));
+    use super::*;
+    use std::fs;
 
-            map.insert(String::from(url), config);
-        }
-        None => warn!("Error reading YAML file"),
+    #[test]
+    fn test_reload_changed_files() {
+        let directory = std::env::temp_dir().join(format!("moker_reload_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let write = |file_name: &str, content: &str| {
+            let path = directory.join(file_name);
+            fs::write(&path, content).unwrap();
+            refs::canonical(&path)
+        };
+        let accounts = write(
+            "accounts.json",
+            r#"{ "url": "accounts", "response": { "body": [] } }"#,
+        );
+        write(
+            "users.json",
+            r#"{ "url": "users", "response": { "body": { "$ref": "shared.json#/user" } } }"#,
+        );
+        let shared = write("shared.json", r#"{ "user": { "name": "ada" } }"#);
+        let search_paths = [directory.to_string_lossy().parse().unwrap()];
+        let reload = |previous: &[LoadedFile], changed: &[&PathBuf]| {
+            let changed = changed.iter().map(|path| (*path).clone()).collect();
+            reload_changed_files(
+                &search_paths,
+                &ScanOptions::default(),
+                &MatchOptions::default(),
+                previous,
+                &changed,
+            )
+        };
+        let route = |files: &[LoadedFile], url: &str| {
+            files
+                .iter()
+                .flat_map(|file| &file.routes)
+                .find(|route| route.url == url)
+                .unwrap()
+                .clone()
+        };
+
+        let loaded = load_route_files(
+            &search_paths,
+            &ScanOptions::default(),
+            &MatchOptions::default(),
+            &[],
+        );
+        assert_eq!(loaded.len(), 2);
+
+        write(
+            "accounts.json",
+            r#"{ "url": "accounts", "response": { "body": [1] } }"#,
+        );
+        let reloaded = reload(&loaded, &[&accounts]);
+        assert!(!Arc::ptr_eq(
+            &route(&loaded, "accounts"),
+            &route(&reloaded, "accounts")
+        ));
+        assert!(Arc::ptr_eq(
+            &route(&loaded, "users"),
+            &route(&reloaded, "users")
+        ));
+
+        write("shared.json", r#"{ "user": { "name": "grace" } }"#);
+        let loaded = reloaded;
+        let reloaded = reload(&loaded, &[&shared]);
+        assert!(Arc::ptr_eq(
+            &route(&loaded, "accounts"),
+            &route(&reloaded, "accounts")
+        ));
+        let users = route(&reloaded, "users");
+        assert_eq!(users.response.get(0).unwrap().body["name"], "grace");
+        assert_eq!(reloaded.len(), 2);
+        _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_fragment_of_another_search_path() {
+        let directory =
+            std::env::temp_dir().join(format!("moker_shared_fragments_{}", std::process::id()));
+        let (shared, mocks) = (directory.join("shared"), directory.join("mocks"));
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(&mocks).unwrap();
+        fs::write(shared.join("user.json"), r#"{ "name": "ada" }"#).unwrap();
+        fs::write(
+            mocks.join("users.json"),
+            r#"{ "url": "users", "response": { "body": { "$ref": "../shared/user.json" } } }"#,
+        )
+        .unwrap();
+
+        // The search path holding the fragment is read first
+        let search_paths = [
+            shared.to_string_lossy().parse().unwrap(),
+            mocks.to_string_lossy().parse().unwrap(),
+        ];
+        let loaded = load_route_files(
+            &search_paths,
+            &ScanOptions::default(),
+            &MatchOptions::default(),
+            &[],
+        );
+
+        assert_eq!(loaded.len(), 1);
+        assert!(loaded[0].error.is_none());
+        assert_eq!(
+            loaded[0].routes[0].response.get(0).unwrap().body["name"],
+            "ada"
+        );
+        _ = fs::remove_dir_all(&directory);
     }
 }
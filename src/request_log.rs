@@ -0,0 +1,289 @@
+use crate::journal::JournalEntry;
+use log::{info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The value written instead of the redacted headers.
+const REDACTED: &str = "[REDACTED]";
+
+/// Headers redacted from the request log, on top of the ones given by the user.
+pub const DEFAULT_REDACTED_HEADERS: [&str; 4] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+/// Options of the request log.
+#[derive(Debug, Clone)]
+pub struct RequestLogOptions {
+    /// The JSON Lines file the exchanges are appended to.
+    pub path: PathBuf,
+    /// The size after which the file is rotated.
+    pub max_size: Option<u64>,
+    /// How long a file is written to before being rotated.
+    pub rotate_every: Option<Duration>,
+    /// The number of rotated files kept next to the current one.
+    pub keep: usize,
+    /// The request and response headers whose values are replaced by `[REDACTED]`, in lowercase.
+    pub redacted_headers: Vec<String>,
+}
+
+/// Appends one JSON record per request and response to a file, rotating it by size or age.
+///
+/// Rotated files are renamed with an increasing suffix: `requests.jsonl.1` is the most recent one.
+pub struct RequestLog {
+    options: RequestLogOptions,
+    file: Mutex<LogFile>,
+}
+
+/// The file being written to.
+struct LogFile {
+    file: File,
+    /// The size of the file, in bytes.
+    size: u64,
+    /// When the file was opened, for time-based rotation.
+    opened: Instant,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        if let Some(directory) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(directory)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            size: file.metadata()?.len(),
+            file,
+            opened: Instant::now(),
+        })
+    }
+}
+
+impl RequestLog {
+    /// Opens the request log, appending to the file if it already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The file to write to, with its rotation and redaction settings.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `RequestLog`, or an error if the file can't be opened.
+    pub fn open(mut options: RequestLogOptions) -> io::Result<Self> {
+        for header in options.redacted_headers.iter_mut() {
+            *header = header.to_lowercase();
+        }
+        let file = LogFile::open(&options.path)?;
+        info!("Logging requests to {}", options.path.display());
+        Ok(Self {
+            options,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends an exchange to the file, rotating it first if needed.
+    ///
+    /// Errors are logged rather than returned, so a full disk doesn't fail the requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The request and its response.
+    pub fn write(&self, entry: &JournalEntry) {
+        let mut entry = entry.clone();
+        for headers in [&mut entry.headers, &mut entry.response.headers] {
+            for (name, value) in headers.iter_mut() {
+                if self.options.redacted_headers.contains(name) {
+                    *value = REDACTED.to_string();
+                }
+            }
+        }
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Unable to serialize request {}: {}", entry.id, err);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if self.should_rotate(&file, line.len() as u64) {
+            match self.rotate() {
+                Ok(rotated) => *file = rotated,
+                Err(err) => warn!(
+                    "Unable to rotate the request log {}: {}",
+                    self.options.path.display(),
+                    err
+                ),
+            }
+        }
+        match file.file.write_all(&line) {
+            Ok(()) => file.size += line.len() as u64,
+            Err(err) => warn!(
+                "Unable to write to the request log {}: {}",
+                self.options.path.display(),
+                err
+            ),
+        }
+    }
+
+    /// Writes the records still held by the operating system to the disk.
+    pub fn flush(&self) {
+        if let Err(err) = self.file.lock().unwrap().file.sync_data() {
+            warn!(
+                "Unable to flush the request log {}: {}",
+                self.options.path.display(),
+                err
+            );
+        }
+    }
+
+    /// Returns `true` if the file is too large or too old to take a record of `length` bytes.
+    fn should_rotate(&self, file: &LogFile, length: u64) -> bool {
+        let too_large = self
+            .options
+            .max_size
+            .is_some_and(|max_size| file.size > 0 && file.size + length > max_size);
+        let too_old = self
+            .options
+            .rotate_every
+            .is_some_and(|rotate_every| file.size > 0 && file.opened.elapsed() >= rotate_every);
+        too_large || too_old
+    }
+
+    /// Shifts the rotated files, drops the oldest ones and opens a new file.
+    fn rotate(&self) -> io::Result<LogFile> {
+        let path = &self.options.path;
+        let rotated = |index: usize| PathBuf::from(format!("{}.{}", path.display(), index));
+
+        if self.options.keep == 0 {
+            fs::remove_file(path)?;
+        } else {
+            _ = fs::remove_file(rotated(self.options.keep));
+            for index in (1..self.options.keep).rev() {
+                if rotated(index).exists() {
+                    fs::rename(rotated(index), rotated(index + 1))?;
+                }
+            }
+            fs::rename(path, rotated(1))?;
+        }
+        info!("Rotated the request log {}", path.display());
+        LogFile::open(path)
+    }
+}
+
+/// Parses a size in bytes, with an optional `KB`, `MB` or `GB` unit.
+///
+/// # Arguments
+///
+/// * `value` - The size, like `512KB` or `10MB`.
+///
+/// # Returns
+///
+/// Returns the number of bytes, or a message if the size is invalid.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let multiplier = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(format!("invalid size unit '{}', use KB, MB or GB", unit)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+/// Parses a duration like `30m`, `1h` or `1day`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    humantime::parse_duration(value).map_err(|err| format!("invalid duration '{}': {}", value, err))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::journal::JournalResponse;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10MB"), Ok(10 << 20));
+        assert_eq!(parse_size("1 kb"), Ok(1024));
+        assert!(parse_size("10TB").is_err());
+        assert!(parse_size("MB").is_err());
+    }
+
+    #[test]
+    fn test_request_log_rotation_and_redaction() {
+        let directory = std::env::temp_dir().join(format!("moker_log_{}", std::process::id()));
+        _ = fs::remove_dir_all(&directory);
+        let entry = JournalEntry {
+            id: 1,
+            time: SystemTime::now(),
+            latency_ms: 1.5,
+            method: String::from("POST"),
+            path: String::from("/payments"),
+            query: String::new(),
+            headers: BTreeMap::from([(
+                String::from("authorization"),
+                String::from("secret-token"),
+            )]),
+            body: "x".repeat(200),
+            route: None,
+            response: JournalResponse {
+                status: 201,
+                headers: BTreeMap::new(),
+                body: String::new(),
+            },
+        };
+        // Redacting makes the records shorter, so two of them fit in a file
+        let length = serde_json::to_vec(&entry).unwrap().len() as u64 + 1;
+        let path = directory.join("requests.jsonl");
+        let log = RequestLog::open(RequestLogOptions {
+            path: path.clone(),
+            max_size: Some(2 * length),
+            rotate_every: None,
+            keep: 2,
+            redacted_headers: vec![String::from("Authorization")],
+        })
+        .unwrap();
+
+        for _ in 0..5 {
+            log.write(&entry);
+        }
+        log.flush();
+
+        let current = fs::read_to_string(&path).unwrap();
+        assert!(!current.contains("secret"));
+        assert!(current.contains(REDACTED));
+        let record: serde_json::Value =
+            serde_json::from_str(current.lines().next().unwrap()).unwrap();
+        assert_eq!(record["response"]["status"], 201);
+
+        // Two records fit in a file, the third file holding the oldest record was dropped
+        assert_eq!(current.lines().count(), 1);
+        let rotated = fs::read_to_string(directory.join("requests.jsonl.2")).unwrap();
+        assert_eq!(rotated.lines().count(), 2);
+        assert!(!directory.join("requests.jsonl.3").exists());
+
+        _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,630 @@
+use crate::app_state::{MatchOptions, ResponseFileType};
+use crate::defaults::DirectoryDefaults;
+use crate::file_reader::{detect_file_type, read_directory, read_routes, ScanOptions, SearchPath};
+use crate::profile;
+use crate::refs;
+use crate::request::RouteConfiguration;
+use crate::rex::generate_regex_from_route;
+use actix_web::http::StatusCode;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Keys allowed at the top level of a route file.
+const ROUTE_KEYS: [&str; 8] = [
+    "name", "group", "method", "url", "headers", "query", "body", "response",
+];
+
+/// Keys allowed in a response.
+const RESPONSE_KEYS: [&str; 5] = ["headers", "body", "status_code", "delay_ms", "raw_body"];
+
+/// HTTP methods a route can be restricted to.
+const HTTP_METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+/// The severity of a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The route file is broken, or part of it is never served.
+    Error,
+    /// The route file works, but probably not the way it was meant to.
+    Warning,
+}
+
+/// A problem found in a route file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The file the problem was found in.
+    pub file: PathBuf,
+    /// The 1-based line and column of the problem, when it can be located.
+    pub position: Option<(usize, usize)>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.position {
+            Some((line, column)) => write!(
+                f,
+                "{}:{}:{}: {}: {}",
+                self.file.display(),
+                line,
+                column,
+                severity,
+                self.message
+            ),
+            None => write!(f, "{}: {}: {}", self.file.display(), severity, self.message),
+        }
+    }
+}
+
+/// A route read from a file, with what's needed to locate its problems.
+struct LoadedRoute {
+    file: PathBuf,
+    /// The content of the file, for formats whose keys can be located.
+    content: Option<String>,
+    /// The position of the route within its file.
+    index: usize,
+    route: RouteConfiguration,
+}
+
+impl LoadedRoute {
+    /// Builds a diagnostic located at the `occurrence`-th appearance of `key` in the file.
+    fn diagnostic(
+        &self,
+        key: &str,
+        occurrence: usize,
+        severity: Severity,
+        message: String,
+    ) -> Diagnostic {
+        let position = self
+            .content
+            .as_deref()
+            .and_then(|content| locate_key(content, key, occurrence));
+        let message = match self.content {
+            Some(_) => message,
+            None => format!("route #{} ({}): {}", self.index, self.route.url, message),
+        };
+        Diagnostic {
+            file: self.file.clone(),
+            position,
+            severity,
+            message,
+        }
+    }
+}
+
+/// Loads every route file of the search paths and reports all the problems found.
+///
+/// The mount prefixes and the defaults of the directories are applied before checking the routes, so conflicts
+/// between search paths are reported too.
+/// This covers parse errors, unknown keys, invalid status codes and methods, URLs that can't be turned
+/// into a regular expression, duplicate routes that are never served and routes matching the same requests.
+///
+/// # Arguments
+///
+/// * `search_paths` - The directories containing the route files, with their mount prefixes.
+/// * `scan_options` - The include and exclude patterns selecting the files to load.
+/// * `match_options` - The query parameters and headers left out of the routes recorded in HAR files.
+///
+/// # Returns
+///
+/// Returns the diagnostics sorted by file and position.
+pub fn validate(
+    search_paths: &[SearchPath],
+    scan_options: &ScanOptions,
+    match_options: &MatchOptions,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut routes = vec![];
+
+    // Every file is read once, the files referenced by the others, from any search path, are only known afterwards
+    let mut fragments = HashSet::new();
+    let mut read_search_paths = vec![];
+    for search_path in search_paths {
+        let mut read_files = vec![];
+        for path in read_directory(&search_path.path, true, scan_options) {
+            let Some((response_file_type, document)) = detect_file_type(&path) else {
+                continue;
+            };
+            let mut file_routes = vec![];
+            let mut file_diagnostics = vec![];
+            load_file(
+                path.clone(),
+                &response_file_type,
+                document,
+                match_options,
+                &mut fragments,
+                &mut file_routes,
+                &mut file_diagnostics,
+            );
+            read_files.push((path, file_routes, file_diagnostics));
+        }
+        read_search_paths.push(read_files);
+    }
+
+    for (search_path, read_files) in search_paths.iter().zip(read_search_paths) {
+        let mut directory_defaults =
+            DirectoryDefaults::new(&search_path.path, search_path.mount.clone());
+
+        for (path, mut file_routes, file_diagnostics) in read_files {
+            if fragments.contains(&refs::canonical(&path)) {
+                continue;
+            }
+            let defaults = directory_defaults.for_file(&path);
+            for route in &mut file_routes {
+                defaults.apply(&mut route.route);
+            }
+            routes.extend(file_routes);
+            diagnostics.extend(file_diagnostics);
+        }
+        for (path, err) in directory_defaults.errors {
+            diagnostics.push(error(&path, None, err));
+        }
+    }
+
+    for route in &routes {
+        check_route(route, &mut diagnostics);
+    }
+    check_conflicts(&routes, &mut diagnostics);
+
+    diagnostics.sort_by(|a, b| (&a.file, a.position).cmp(&(&b.file, b.position)));
+    diagnostics
+}
+
+/// Reads a route file with the overlay of the selected profile applied, reporting parse errors and unknown keys.
+///
+/// The `document` parsed by `detect_file_type` is only reused for the imported formats, the other ones are parsed
+/// again from their content to locate their errors.
+fn load_file(
+    path: PathBuf,
+    response_file_type: &ResponseFileType,
+    document: Option<Value>,
+    match_options: &MatchOptions,
+    dependencies: &mut HashSet<PathBuf>,
+    routes: &mut Vec<LoadedRoute>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) => {
+            diagnostics.push(error(&path, None, format!("unable to read file: {}", err)));
+            return;
+        }
+    };
+
+    let parse: fn(&str) -> ParseResult = match response_file_type {
+        ResponseFileType::Json(_) => parse_json,
+        ResponseFileType::Yaml(_) => parse_yaml,
+        ResponseFileType::Toml(_) => parse_toml,
+        ResponseFileType::Json5(_) => parse_json5,
+        _ => {
+            // Imported formats are translated, their keys can't be traced back to the file
+            match read_routes(response_file_type, document, match_options, dependencies) {
+                Ok(imported) => {
+                    routes.extend(imported.into_iter().enumerate().map(|(index, route)| {
+                        LoadedRoute {
+                            file: path.clone(),
+                            content: None,
+                            index,
+                            route,
+                        }
+                    }))
+                }
+                Err(err) => {
+                    let position = serde_json::from_str::<Value>(&content)
+                        .err()
+                        .map(|err| (err.line(), err.column()));
+                    diagnostics.push(error(&path, position, strip_position(&err.to_string())));
+                }
+            }
+            return;
+        }
+    };
+
+    // Unknown keys are reported even if the route can't be read, a typo is often the cause of a missing field
+    let (value, mut route) = parse(&content);
+    if let Some(mut value) = value {
+        check_unknown_keys(&value, &content, &path, diagnostics);
+
+        // The overlay can complete a route file, the merged document is the one that must be valid
+        let overlay = profile::overlay_path(&path).filter(|overlay| overlay.is_file());
+        if let Some(overlay) = &overlay {
+            let Some(patch) = read_overlay(overlay, parse, diagnostics) else {
+                return;
+            };
+            profile::merge_patch(&mut value, patch);
+        }
+
+        // The route is checked as it is served, with its fragments included and its placeholders resolved
+        if let Err(err) = refs::resolve_refs(&mut value, &path, dependencies) {
+            let position = locate_key(&content, "$ref", 0);
+            diagnostics.push(error(&path, position, err));
+        } else if let Err(err) = profile::interpolate(&mut value) {
+            let position = locate_key(&content, &format!("${{{}", err.0), 0);
+            diagnostics.push(error(&path, position, err.to_string()));
+        } else if route.is_ok() || overlay.is_some() {
+            route = serde_json::from_value(value).map_err(|err| (err.to_string(), None));
+        }
+    }
+    match route {
+        Ok(route) => routes.push(LoadedRoute {
+            file: path,
+            content: Some(content),
+            index: 0,
+            route,
+        }),
+        Err((message, position)) => diagnostics.push(error(&path, position, message)),
+    }
+}
+
+/// Reads the overlay of a route file, reporting parse errors and unknown keys.
+///
+/// Returns `None` if the overlay can't be read or parsed.
+fn read_overlay(
+    overlay: &Path,
+    parse: fn(&str) -> ParseResult,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Value> {
+    let content = match fs::read_to_string(overlay) {
+        Ok(content) => content,
+        Err(err) => {
+            diagnostics.push(error(
+                overlay,
+                None,
+                format!("unable to read file: {}", err),
+            ));
+            return None;
+        }
+    };
+    match parse(&content) {
+        (Some(patch), _) => {
+            check_unknown_keys(&patch, &content, overlay, diagnostics);
+            Some(patch)
+        }
+        (None, route) => {
+            if let Err((message, position)) = route {
+                diagnostics.push(error(overlay, position, message));
+            }
+            None
+        }
+    }
+}
+
+/// The document parsed as a generic value, if it is well-formed, and the route it defines.
+type ParseResult = (
+    Option<Value>,
+    Result<RouteConfiguration, (String, Option<(usize, usize)>)>,
+);
+
+fn parse_json(content: &str) -> ParseResult {
+    let route = serde_json::from_str(content).map_err(|err| {
+        (
+            strip_position(&err.to_string()),
+            Some((err.line(), err.column())),
+        )
+    });
+    (serde_json::from_str(content).ok(), route)
+}
+
+fn parse_yaml(content: &str) -> ParseResult {
+    let route = serde_yaml::from_str(content).map_err(|err| {
+        let position = err
+            .location()
+            .map(|location| (location.line(), location.column()));
+        (strip_position(&err.to_string()), position)
+    });
+    (serde_yaml::from_str(content).ok(), route)
+}
+
+fn parse_toml(content: &str) -> ParseResult {
+    let route = toml::from_str(content).map_err(|err| {
+        let position = err
+            .span()
+            .map(|span| position_of_offset(content, span.start));
+        (err.message().to_string(), position)
+    });
+    (toml::from_str(content).ok(), route)
+}
+
+fn parse_json5(content: &str) -> ParseResult {
+    let route = json5::from_str(content).map_err(|err| match err {
+        json5::Error::Message { msg, location } => (
+            msg,
+            location.map(|location| (location.line, location.column)),
+        ),
+    });
+    (json5::from_str(content).ok(), route)
+}
+
+/// Reports the keys of a route file moker doesn't know, which are usually typos.
+fn check_unknown_keys(
+    value: &Value,
+    content: &str,
+    path: &Path,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut unknown = vec![];
+    for key in value.as_object().into_iter().flat_map(|route| route.keys()) {
+        if !ROUTE_KEYS.contains(&key.as_str()) {
+            unknown.push((key, format!("unknown key '{}'", key)));
+        }
+    }
+
+    let responses: Vec<&Value> = match &value["response"] {
+        Value::Array(responses) => responses.iter().collect(),
+        response => vec![response],
+    };
+    for response in responses {
+        for key in response
+            .as_object()
+            .into_iter()
+            .flat_map(|response| response.keys())
+        {
+            if !RESPONSE_KEYS.contains(&key.as_str()) {
+                unknown.push((key, format!("unknown key '{}' in response", key)));
+            }
+        }
+    }
+
+    // A key repeated in several responses is reported at each of its occurrences
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    for (key, message) in unknown {
+        let occurrence = occurrences.entry(key.as_str()).or_default();
+        diagnostics.push(error(path, locate_key(content, key, *occurrence), message));
+        *occurrence += 1;
+    }
+}
+
+/// Reports invalid URLs, methods and status codes of a route.
+fn check_route(loaded: &LoadedRoute, diagnostics: &mut Vec<Diagnostic>) {
+    let route = &loaded.route;
+
+    if let Err(err) = generate_regex_from_route(route.url.trim_matches('/')) {
+        let message = format!(
+            "URL '{}' can't be matched: {}",
+            route.url,
+            last_line(&err.to_string())
+        );
+        diagnostics.push(loaded.diagnostic("url", 0, Severity::Error, message));
+    }
+
+    match &route.method {
+        None => {}
+        Some(Value::String(method)) => check_method(loaded, method, diagnostics),
+        Some(Value::Array(methods)) => {
+            for method in methods {
+                match method.as_str() {
+                    Some(method) => check_method(loaded, method, diagnostics),
+                    None => diagnostics.push(loaded.diagnostic(
+                        "method",
+                        0,
+                        Severity::Error,
+                        format!("method {} must be a string", method),
+                    )),
+                }
+            }
+        }
+        Some(method) => diagnostics.push(loaded.diagnostic(
+            "method",
+            0,
+            Severity::Error,
+            format!("method {} must be a string or a list of strings", method),
+        )),
+    }
+
+    if route.response.iter().next().is_none() {
+        diagnostics.push(loaded.diagnostic(
+            "response",
+            0,
+            Severity::Error,
+            String::from("response list is empty"),
+        ));
+    }
+
+    let mut status_codes = 0;
+    for response in route.response.iter() {
+        let Some(status_code) = response.status_code else {
+            continue;
+        };
+        let is_valid = u16::try_from(status_code)
+            .ok()
+            .is_some_and(|code| StatusCode::from_u16(code).is_ok());
+        if !is_valid {
+            diagnostics.push(loaded.diagnostic(
+                "status_code",
+                status_codes,
+                Severity::Error,
+                format!("invalid status code {}", status_code),
+            ));
+        }
+        status_codes += 1;
+    }
+}
+
+fn check_method(loaded: &LoadedRoute, method: &str, diagnostics: &mut Vec<Diagnostic>) {
+    if !HTTP_METHODS.contains(&method.to_uppercase().as_str()) {
+        diagnostics.push(loaded.diagnostic(
+            "method",
+            0,
+            Severity::Error,
+            format!("unknown HTTP method '{}'", method),
+        ));
+    }
+}
+
+/// Reports routes that are never served because an identical route comes first, and routes matching the same requests.
+fn check_conflicts(routes: &[LoadedRoute], diagnostics: &mut Vec<Diagnostic>) {
+    for (i, loaded) in routes.iter().enumerate() {
+        let route = &loaded.route;
+        let url = route.url.trim_matches('/');
+
+        for other in &routes[..i] {
+            let other_url = other.route.url.trim_matches('/');
+            if !route.methods_overlap(&other.route) || !urls_overlap(url, other_url) {
+                continue;
+            }
+
+            let other_location = match other
+                .content
+                .as_deref()
+                .and_then(|content| locate_key(content, "url", 0))
+            {
+                Some((line, column)) => format!("{}:{}:{}", other.file.display(), line, column),
+                None => other.file.display().to_string(),
+            };
+
+            if url == other_url {
+                let same_guards = route.headers == other.route.headers
+                    && route.query == other.route.query
+                    && route.body == other.route.body;
+                if same_guards {
+                    diagnostics.push(loaded.diagnostic(
+                        "url",
+                        0,
+                        Severity::Error,
+                        format!(
+                            "duplicate route '{}', already defined in {}; this definition is never served",
+                            url, other_location
+                        ),
+                    ));
+                }
+            } else {
+                diagnostics.push(loaded.diagnostic(
+                    "url",
+                    0,
+                    Severity::Warning,
+                    format!(
+                        "route '{}' conflicts with '{}' defined in {}; both match the same requests and '{}' is tried first, routes being tried in the alphabetical order of their URL",
+                        url, other_url, other_location, url.min(other_url)
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Returns `true` if some request path matches both routes.
+fn urls_overlap(url: &str, other: &str) -> bool {
+    let is_param = |segment: &str| segment.starts_with('{') && segment.ends_with('}');
+    let segments: Vec<&str> = url.split('/').collect();
+    let other: Vec<&str> = other.split('/').collect();
+    segments.len() == other.len()
+        && segments
+            .iter()
+            .zip(other.iter())
+            .all(|(a, b)| a == b || is_param(a) || is_param(b))
+}
+
+fn error(path: &Path, position: Option<(usize, usize)>, message: String) -> Diagnostic {
+    Diagnostic {
+        file: path.to_path_buf(),
+        position,
+        severity: Severity::Error,
+        message,
+    }
+}
+
+/// Returns the 1-based line and column of the `occurrence`-th appearance of a key, written as a whole word.
+fn locate_key(content: &str, key: &str, occurrence: usize) -> Option<(usize, usize)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    content
+        .match_indices(key)
+        .filter(|(offset, _)| {
+            let before = content[..*offset].chars().next_back();
+            let after = content[offset + key.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        })
+        .nth(occurrence)
+        .map(|(offset, _)| position_of_offset(content, offset))
+}
+
+/// Converts a byte offset into a 1-based line and column.
+fn position_of_offset(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    (line, column)
+}
+
+/// Removes the `at line X column Y` suffix serde adds to its messages, the position is reported separately.
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// Returns the last line of a multi-line error message, which holds the actual error.
+fn last_line(message: &str) -> &str {
+    message.lines().last().unwrap_or(message)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_locate_key() {
+        let content = "{\n  \"url\": \"a\",\n  \"response\": {\n    \"status_code\": 200\n  }\n}";
+
+        assert_eq!(locate_key(content, "url", 0), Some((2, 4)));
+        assert_eq!(locate_key(content, "status_code", 0), Some((4, 6)));
+        assert_eq!(locate_key(content, "code", 0), None);
+    }
+
+    #[test]
+    fn test_validate_imported_har() {
+        let output = std::env::temp_dir().join(format!("moker_imported_{}", std::process::id()));
+        crate::import_har(
+            Path::new("./example/har/orders.har"),
+            &output,
+            &MatchOptions::default(),
+        )
+        .unwrap();
+
+        let search_paths = [output.to_string_lossy().parse().unwrap()];
+        let diagnostics = validate(
+            &search_paths,
+            &ScanOptions::default(),
+            &MatchOptions::default(),
+        );
+
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.severity != Severity::Error));
+        _ = fs::remove_dir_all(&output);
+    }
+
+    #[test]
+    fn test_check_unknown_keys() {
+        let content = "{\n  \"url\": \"a\",\n  \"response\": [\n    { \"stauts\": 200 },\n    { \"stauts\": 201 }\n  ]\n}";
+        let value: Value = serde_json::from_str(content).unwrap();
+        let mut diagnostics = vec![];
+
+        check_unknown_keys(&value, content, Path::new("a.json"), &mut diagnostics);
+
+        let positions: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.position)
+            .collect();
+        assert_eq!(positions, vec![Some((4, 8)), Some((5, 8))]);
+    }
+
+    #[test]
+    fn test_urls_overlap() {
+        assert!(urls_overlap("users/{id}", "users/{user_id}"));
+        assert!(urls_overlap("users/me", "users/{id}"));
+        assert!(!urls_overlap("users/me", "users/{id}/cards"));
+        assert!(!urls_overlap("users/me", "accounts/me"));
+    }
+}
@@ -0,0 +1,388 @@
+use crate::request::{Response, Responses, RouteConfiguration, SERVER_MANAGED_HEADERS};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::warn;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Characters that turn a WireMock URL pattern segment into a regular expression.
+const REGEX_CHARACTERS: &[char] = &[
+    '.', '*', '+', '?', '[', ']', '(', ')', '{', '}', '|', '\\', '^', '$',
+];
+
+/// Returns `true` if a JSON document is a WireMock stub mapping, or a list of them.
+///
+/// # Arguments
+///
+/// * `value` - The parsed JSON document.
+pub fn is_wiremock_mapping(value: &Value) -> bool {
+    value["mappings"].is_array() || (value["request"].is_object() && value["response"].is_object())
+}
+
+/// Converts WireMock stub mappings into route configurations.
+///
+/// URL, header, query and body matchers are translated into moker guards when moker supports them.
+/// Anything that can't be translated is reported with a warning, and mappings that can't be served
+/// at all (proxies, faults, or requests without a URL) are skipped.
+///
+/// # Arguments
+///
+/// * `value` - The parsed mapping file, holding a single mapping or a `mappings` list.
+/// * `path` - The path of the mapping file, used to resolve `bodyFileName` from the sibling `__files` directory.
+///
+/// # Returns
+///
+/// Returns the route configurations in the order the mappings appear in the file.
+pub fn wiremock_to_routes(value: Value, path: &Path) -> Vec<RouteConfiguration> {
+    let mappings = match value {
+        Value::Object(mut value) if value.contains_key("mappings") => {
+            match value.remove("mappings") {
+                Some(Value::Array(mappings)) => mappings,
+                _ => vec![],
+            }
+        }
+        mapping => vec![mapping],
+    };
+
+    mappings
+        .iter()
+        .filter_map(|mapping| mapping_to_route(mapping, path))
+        .collect()
+}
+
+/// Converts a single stub mapping.
+fn mapping_to_route(mapping: &Value, path: &Path) -> Option<RouteConfiguration> {
+    let request = &mapping["request"];
+    let response = &mapping["response"];
+
+    let name = mapping["name"]
+        .as_str()
+        .or(mapping["id"].as_str())
+        .or(mapping["uuid"].as_str())
+        .map(String::from);
+    let label = format!(
+        "{:?} mapping '{}'",
+        path,
+        name.as_deref().unwrap_or("unnamed")
+    );
+
+    for unsupported in ["proxyBaseUrl", "fault"] {
+        if !response[unsupported].is_null() {
+            warn!(
+                "{}: '{}' responses are not supported, skipping",
+                label, unsupported
+            );
+            return None;
+        }
+    }
+    if !mapping["scenarioName"].is_null() {
+        warn!(
+            "{}: scenarios are not supported, the mapping is served whatever the scenario state",
+            label
+        );
+    }
+    if !response["transformers"].is_null() {
+        warn!(
+            "{}: response transformers are not supported, the response is served as-is",
+            label
+        );
+    }
+
+    let (url, mut query) = translate_url(request, &label)?;
+    query.extend(translate_query(&request["queryParameters"], &label));
+
+    let method = match request["method"].as_str() {
+        None | Some("ANY") => None,
+        Some(method) => Some(Value::String(method.to_string())),
+    };
+
+    let headers = translate_headers(&request["headers"], &label);
+
+    Some(RouteConfiguration {
+        name,
+        group: None,
+        method,
+        url,
+        headers: (!headers.is_empty()).then_some(headers),
+        query: (!query.is_empty()).then_some(query),
+        body: translate_body_patterns(&request["bodyPatterns"], &label),
+        response: Responses::Single(translate_response(response, path, &label)),
+    })
+}
+
+/// Translates the URL matcher of a request into a moker route and the query parameters it requires.
+fn translate_url(request: &Value, label: &str) -> Option<(String, HashMap<String, String>)> {
+    if let Some(url) = request["url"].as_str() {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let query = query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        return Some((path.trim_matches('/').to_string(), query));
+    }
+
+    if let Some(url) = request["urlPath"]
+        .as_str()
+        .or(request["urlPathTemplate"].as_str())
+    {
+        return Some((url.trim_matches('/').to_string(), HashMap::new()));
+    }
+
+    if let Some(pattern) = request["urlPathPattern"]
+        .as_str()
+        .or(request["urlPattern"].as_str())
+    {
+        let route = route_from_pattern(pattern);
+        warn!(
+            "{}: regular expression URLs are not supported, '{}' is served as '{}'",
+            label, pattern, route
+        );
+        return Some((route, HashMap::new()));
+    }
+
+    warn!(
+        "{}: mappings matching any URL are not supported, skipping",
+        label
+    );
+    None
+}
+
+/// Approximates a URL regular expression with a moker route, turning every segment holding a regular expression into a placeholder.
+fn route_from_pattern(pattern: &str) -> String {
+    let pattern = pattern.trim_start_matches('^').trim_end_matches('$');
+    let pattern = pattern.split("\\?").next().unwrap_or_default();
+
+    let mut placeholders = 0;
+    pattern
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            if segment.contains(REGEX_CHARACTERS) {
+                placeholders += 1;
+                format!("{{param{}}}", placeholders)
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Translates header matchers into header guards. moker only checks that a header is present.
+fn translate_headers(matchers: &Value, label: &str) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    for (name, matcher) in matchers.as_object().into_iter().flatten() {
+        if matcher["absent"].as_bool() == Some(true) {
+            warn!(
+                "{}: 'absent' matcher of header '{}' is not supported, ignoring it",
+                label, name
+            );
+            continue;
+        }
+        let value = matcher["equalTo"].as_str().unwrap_or_default();
+        warn!(
+            "{}: header '{}' is only checked for presence, its value isn't compared",
+            label, name
+        );
+        headers.insert(name.to_lowercase(), value.to_string());
+    }
+    headers
+}
+
+/// Translates query parameter matchers into query guards. Only `equalTo` can be translated.
+fn translate_query(matchers: &Value, label: &str) -> HashMap<String, String> {
+    let mut query = HashMap::new();
+    for (name, matcher) in matchers.as_object().into_iter().flatten() {
+        match matcher["equalTo"].as_str() {
+            Some(value) => {
+                query.insert(name.clone(), value.to_string());
+            }
+            None => warn!(
+                "{}: only 'equalTo' query matchers are supported, ignoring '{}': {}",
+                label, name, matcher
+            ),
+        }
+    }
+    query
+}
+
+/// Translates body patterns into a body guard.
+///
+/// `equalToJson` becomes a JSON guard, `contains` and `equalTo` a string guard. Only the first translatable pattern is kept.
+fn translate_body_patterns(patterns: &Value, label: &str) -> Option<Value> {
+    let mut body = None;
+    for pattern in patterns.as_array().into_iter().flatten() {
+        let guard = match (
+            &pattern["equalToJson"],
+            pattern["contains"].as_str().or(pattern["equalTo"].as_str()),
+        ) {
+            (Value::String(json), _) => serde_json::from_str(json).ok(),
+            (Value::Null, Some(text)) => Some(Value::String(text.to_string())),
+            (Value::Null, None) => None,
+            (json, _) => Some(json.clone()),
+        };
+
+        match guard {
+            Some(guard) if body.is_none() => body = Some(guard),
+            Some(_) => warn!(
+                "{}: only one body pattern is supported, ignoring {}",
+                label, pattern
+            ),
+            None => warn!(
+                "{}: body pattern is not supported, ignoring {}",
+                label, pattern
+            ),
+        }
+    }
+    body
+}
+
+/// Translates a response definition.
+fn translate_response(response: &Value, path: &Path, label: &str) -> Response {
+    let mut headers = HashMap::new();
+    for (name, value) in response["headers"].as_object().into_iter().flatten() {
+        if SERVER_MANAGED_HEADERS.contains(&name.to_lowercase().as_str()) {
+            continue;
+        }
+        let value = match value {
+            Value::Array(values) => values
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<&str>>()
+                .join(", "),
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        headers.insert(name.clone(), value);
+    }
+
+    let body = if !response["jsonBody"].is_null() {
+        response["jsonBody"].clone()
+    } else if let Some(body) = response["body"].as_str() {
+        Value::String(body.to_string())
+    } else if let Some(body) = response["base64Body"].as_str() {
+        match STANDARD.decode(body).map(String::from_utf8) {
+            Ok(Ok(body)) => Value::String(body),
+            _ => {
+                warn!(
+                    "{}: binary bodies are not supported, serving an empty body",
+                    label
+                );
+                Value::String(String::new())
+            }
+        }
+    } else if let Some(file_name) = response["bodyFileName"].as_str() {
+        Value::String(read_body_file(file_name, path, label))
+    } else {
+        Value::String(String::new())
+    };
+
+    if !response["delayDistribution"].is_null() {
+        warn!(
+            "{}: random delays are not supported, ignoring 'delayDistribution'",
+            label
+        );
+    }
+
+    Response {
+        headers: (!headers.is_empty()).then_some(headers),
+        body,
+        status_code: Some(response["status"].as_i64().unwrap_or(200) as i32),
+        delay_ms: response["fixedDelayMilliseconds"].as_u64(),
+        raw_body: true,
+    }
+}
+
+/// Reads a `bodyFileName`, looked up in the `__files` directory next to the `mappings` directory like WireMock does,
+/// then next to the mapping file itself.
+fn read_body_file(file_name: &str, path: &Path, label: &str) -> String {
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let candidates = [
+        directory.join("../__files").join(file_name),
+        directory.join("__files").join(file_name),
+        directory.join(file_name),
+    ];
+
+    match candidates
+        .iter()
+        .find_map(|candidate| fs::read_to_string(candidate).ok())
+    {
+        Some(body) => body,
+        None => {
+            warn!(
+                "{}: unable to read body file '{}', serving an empty body",
+                label, file_name
+            );
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_route_from_pattern() {
+        let patterns = vec![
+            ("/users/[0-9]+", "users/{param1}"),
+            (
+                "^/orders/.*/items/(\\w+)$",
+                "orders/{param1}/items/{param2}",
+            ),
+            ("/accounts/summary\\?page=.*", "accounts/summary"),
+        ];
+
+        for (pattern, route) in patterns {
+            assert_eq!(route_from_pattern(pattern), route);
+        }
+    }
+
+    #[test]
+    fn test_wiremock_to_routes() {
+        let mappings = json!({
+            "mappings": [
+                {
+                    "name": "Create user",
+                    "request": {
+                        "method": "POST",
+                        "url": "/users?source=web",
+                        "headers": { "Authorization": { "matches": "Bearer .*" } },
+                        "bodyPatterns": [{ "equalToJson": "{\"role\": \"admin\"}" }]
+                    },
+                    "response": {
+                        "status": 201,
+                        "jsonBody": { "id": "1" },
+                        "fixedDelayMilliseconds": 50
+                    }
+                },
+                {
+                    "request": { "urlPath": "/users/1" },
+                    "response": { "proxyBaseUrl": "https://example.com" }
+                }
+            ]
+        });
+
+        let routes = wiremock_to_routes(mappings, Path::new("mappings/users.json"));
+
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+        assert_eq!(route.url, "users");
+        assert_eq!(route.method, Some(json!("POST")));
+        assert_eq!(route.query.as_ref().unwrap()["source"], "web");
+        assert!(route
+            .headers
+            .as_ref()
+            .unwrap()
+            .contains_key("authorization"));
+        assert_eq!(route.body, Some(json!({ "role": "admin" })));
+        let response = route.response.get(0).unwrap();
+        assert_eq!(response.status_code, Some(201));
+        assert_eq!(response.delay_ms, Some(50));
+        assert_eq!(response.body, json!({ "id": "1" }));
+    }
+}